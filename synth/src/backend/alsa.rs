@@ -0,0 +1,64 @@
+use super::midir_listener::MidirListener;
+use super::Backend;
+use crate::config::AlsaConfig;
+use crate::synth::{MidiSender, Synth};
+use alsa::pcm::{Access, Format, HwParams, PCM};
+use alsa::{Direction, ValueOr};
+use std::error::Error;
+
+/// Plays directly on an ALSA PCM device (which includes PipeWire's ALSA
+/// plugin) and reads MIDI from the ALSA sequencer, so no JACK server or
+/// `a2jmidid` bridge is needed.
+pub struct AlsaBackend {
+    pcm: PCM,
+    sample_rate: u32,
+    period_size: usize,
+    client_name: String,
+}
+
+impl AlsaBackend {
+    pub fn new(config: &AlsaConfig) -> Result<Self, alsa::Error> {
+        let pcm = PCM::new(&config.device, Direction::Playback, false)?;
+        let (sample_rate, period_size) = {
+            let hwp = HwParams::any(&pcm)?;
+            hwp.set_channels(2)?;
+            hwp.set_rate(config.sample_rate, ValueOr::Nearest)?;
+            hwp.set_format(Format::float())?;
+            hwp.set_access(Access::RWInterleaved)?;
+            hwp.set_period_size_near(config.period_size as alsa::pcm::Frames, ValueOr::Nearest)?;
+            hwp.set_buffer_size_near(config.period_size as alsa::pcm::Frames * 4)?;
+            pcm.hw_params(&hwp)?;
+            (hwp.get_rate()?, hwp.get_period_size()? as usize)
+        };
+        Ok(Self {
+            pcm,
+            sample_rate,
+            period_size,
+            client_name: config.client_name.clone(),
+        })
+    }
+}
+
+impl Backend for AlsaBackend {
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate as f32
+    }
+
+    fn run(self: Box<Self>, mut synth: Synth, midi: MidiSender) -> Result<(), Box<dyn Error>> {
+        MidirListener::new(&self.client_name, midi).start();
+        let io = self.pcm.io_f32()?;
+        let mut left = vec![0.0; self.period_size];
+        let mut right = vec![0.0; self.period_size];
+        let mut buffer = vec![0.0; self.period_size * 2];
+        loop {
+            synth.render(&mut left, &mut right);
+            for ((frame, left), right) in buffer.chunks_exact_mut(2).zip(&left).zip(&right) {
+                (frame[0], frame[1]) = (*left, *right);
+            }
+            if let Err(e) = io.writei(&buffer) {
+                println!("ALSA write error: {}", e);
+                self.pcm.try_recover(e, true)?;
+            }
+        }
+    }
+}
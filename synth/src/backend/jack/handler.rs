@@ -0,0 +1,47 @@
+use crate::synth::{MidiSender, Synth};
+use jack::{AudioOut, Client, MidiIn, Port, ProcessHandler, ProcessScope};
+
+pub struct JackHandler {
+    synth: Synth,
+    midi: MidiSender,
+    midi_in_port: Port<MidiIn>,
+    audio_out_l_port: Port<AudioOut>,
+    audio_out_r_port: Port<AudioOut>,
+}
+
+impl JackHandler {
+    pub fn new(
+        synth: Synth,
+        midi: MidiSender,
+        midi_in_port: Port<MidiIn>,
+        audio_out_l_port: Port<AudioOut>,
+        audio_out_r_port: Port<AudioOut>,
+    ) -> Self {
+        Self {
+            synth,
+            midi,
+            midi_in_port,
+            audio_out_l_port,
+            audio_out_r_port,
+        }
+    }
+}
+
+impl ProcessHandler for JackHandler {
+    fn process(&mut self, _: &Client, ps: &ProcessScope) -> jack::Control {
+        // Events take effect one period later at their exact offset, which
+        // leaves the control thread a whole period to prepare them. Pipes
+        // can't be built here without allocating. If the control thread
+        // misses that deadline the synth applies them late and logs it.
+        let period_start = self.synth.frame() + ps.n_frames() as u64;
+        for event in self.midi_in_port.iter(ps) {
+            self.midi
+                .send_at(period_start + event.time as u64, event.bytes);
+        }
+        self.synth.render(
+            self.audio_out_l_port.as_mut_slice(ps),
+            self.audio_out_r_port.as_mut_slice(ps),
+        );
+        jack::Control::Continue
+    }
+}
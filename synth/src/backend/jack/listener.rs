@@ -1,4 +1,4 @@
-use crate::jack_handler::JackHandler;
+use super::JackHandler;
 use jack::{Port, PortFlags, Unowned};
 use std::{
     collections::HashSet,
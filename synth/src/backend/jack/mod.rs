@@ -0,0 +1,79 @@
+mod handler;
+mod listener;
+use super::Backend;
+use crate::config::JackConfig;
+use crate::synth::{MidiSender, Synth};
+use handler::JackHandler;
+use jack::{AudioOut, MidiIn, Port};
+use listener::MidiListener;
+use std::error::Error;
+
+pub struct JackBackend {
+    client: jack::Client,
+    audio_out_l_port: Port<AudioOut>,
+    audio_out_r_port: Port<AudioOut>,
+    midi_in_port: Port<MidiIn>,
+    client_name: String,
+    audio_out_l_port_name: String,
+    audio_out_r_port_name: String,
+    midi_in_port_name: String,
+    system_audio_l_port_name: String,
+    system_audio_r_port_name: String,
+}
+
+impl JackBackend {
+    pub fn new(config: &JackConfig) -> Result<Self, jack::Error> {
+        let (client, _) =
+            jack::Client::new(&config.client_name, jack::ClientOptions::NO_START_SERVER)?;
+        let audio_out_l_port =
+            client.register_port(&config.audio_out_l_port_name, AudioOut::default())?;
+        let audio_out_r_port =
+            client.register_port(&config.audio_out_r_port_name, AudioOut::default())?;
+        let midi_in_port = client.register_port(&config.midi_in_port_name, MidiIn::default())?;
+        Ok(Self {
+            client,
+            audio_out_l_port,
+            audio_out_r_port,
+            midi_in_port,
+            client_name: config.client_name.clone(),
+            audio_out_l_port_name: config.audio_out_l_port_name.clone(),
+            audio_out_r_port_name: config.audio_out_r_port_name.clone(),
+            midi_in_port_name: config.midi_in_port_name.clone(),
+            system_audio_l_port_name: config.system_audio_l_port_name.clone(),
+            system_audio_r_port_name: config.system_audio_r_port_name.clone(),
+        })
+    }
+}
+
+impl Backend for JackBackend {
+    fn sample_rate(&self) -> f32 {
+        self.client.sample_rate() as f32
+    }
+
+    fn run(self: Box<Self>, synth: Synth, midi: MidiSender) -> Result<(), Box<dyn Error>> {
+        let handler = JackHandler::new(
+            synth,
+            midi,
+            self.midi_in_port,
+            self.audio_out_l_port,
+            self.audio_out_r_port,
+        );
+        let active_client = self.client.activate_async((), handler)?;
+        let full_audio_out_l_port_name =
+            format!("{}:{}", self.client_name, self.audio_out_l_port_name);
+        let full_audio_out_r_port_name =
+            format!("{}:{}", self.client_name, self.audio_out_r_port_name);
+        let full_midi_in_port_name = format!("{}:{}", self.client_name, self.midi_in_port_name);
+        active_client
+            .as_client()
+            .connect_ports_by_name(&full_audio_out_l_port_name, &self.system_audio_l_port_name)?;
+        active_client
+            .as_client()
+            .connect_ports_by_name(&full_audio_out_r_port_name, &self.system_audio_r_port_name)?;
+        MidiListener::new(active_client, full_midi_in_port_name)
+            .start()
+            .join()
+            .map_err(|_| "MIDI listener thread panicked")?;
+        Ok(())
+    }
+}
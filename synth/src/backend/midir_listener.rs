@@ -0,0 +1,66 @@
+use crate::synth::MidiSender;
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Connects every MIDI input port on the system to the synth, picking up
+/// devices as they are plugged in. Used by backends without JACK MIDI.
+pub struct MidirListener {
+    client_name: String,
+    /// Shared by every port's callback.
+    midi: Arc<Mutex<MidiSender>>,
+    connections: Mutex<HashMap<String, MidiInputConnection<()>>>,
+}
+
+impl MidirListener {
+    pub fn new(client_name: &str, midi: MidiSender) -> Arc<Self> {
+        Arc::new(Self {
+            client_name: client_name.to_string(),
+            midi: Arc::new(Mutex::new(midi)),
+            connections: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn start(self: Arc<Self>) -> thread::JoinHandle<()> {
+        let clone = self.clone();
+        thread::spawn(move || loop {
+            if let Err(e) = clone.poll() {
+                println!("Error polling MIDI ports: {:?}", e);
+            }
+            thread::sleep(Duration::from_secs(1));
+        })
+    }
+
+    fn poll(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let input = MidiInput::new(&self.client_name)?;
+        let ports: HashMap<String, _> = input
+            .ports()
+            .into_iter()
+            .filter_map(|port| Some((input.port_name(&port).ok()?, port)))
+            .filter(|(name, _)| !name.starts_with(&self.client_name))
+            .collect();
+        let mut connections = self.connections.lock().unwrap();
+        connections.retain(|name, _| ports.contains_key(name));
+        for (port_name, port) in ports.iter() {
+            if connections.contains_key(port_name) {
+                continue;
+            }
+            println!("Connecting to MIDI port: {}", port_name);
+            let mut input = MidiInput::new(&self.client_name)?;
+            input.ignore(Ignore::None);
+            let midi = self.midi.clone();
+            let connection = input.connect(
+                port,
+                &self.client_name,
+                move |_, bytes, _| midi.lock().unwrap().send(bytes),
+                (),
+            )?;
+            connections.insert(port_name.clone(), connection);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+mod alsa;
+mod jack;
+mod midir_listener;
+mod null;
+use crate::config::{BackendKind, Config};
+use crate::synth::{MidiSender, Synth};
+use std::error::Error;
+
+/// An audio output plus MIDI input that drives a `Synth`.
+///
+/// Backends are created before the synth so the synth can be built at the
+/// sample rate the device actually runs at.
+pub trait Backend {
+    fn sample_rate(&self) -> f32;
+
+    /// Starts audio and MIDI processing and blocks for the lifetime of the
+    /// process. `synth` moves to the audio thread; incoming MIDI goes to
+    /// `midi`.
+    fn run(self: Box<Self>, synth: Synth, midi: MidiSender) -> Result<(), Box<dyn Error>>;
+}
+
+pub fn create(config: &Config) -> Result<Box<dyn Backend>, Box<dyn Error>> {
+    match config.audio.backend {
+        BackendKind::Jack => {
+            let jack_config = config
+                .jack
+                .as_ref()
+                .ok_or("The jack backend requires a [jack] section")?;
+            Ok(Box::new(jack::JackBackend::new(jack_config)?))
+        }
+        BackendKind::Alsa => Ok(Box::new(alsa::AlsaBackend::new(&config.alsa)?)),
+        BackendKind::Null => Ok(Box::new(null::NullBackend::new(&config.null))),
+    }
+}
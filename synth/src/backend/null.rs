@@ -0,0 +1,46 @@
+use super::midir_listener::MidirListener;
+use super::Backend;
+use crate::config::NullConfig;
+use crate::synth::{MidiSender, Synth};
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Renders and discards audio on a timer, at the pace a real device would
+/// consume it. Useful on headless machines and for exercising MIDI handling.
+pub struct NullBackend {
+    sample_rate: u32,
+    period_size: usize,
+    client_name: String,
+}
+
+impl NullBackend {
+    pub fn new(config: &NullConfig) -> Self {
+        Self {
+            sample_rate: config.sample_rate,
+            period_size: config.period_size,
+            client_name: config.client_name.clone(),
+        }
+    }
+}
+
+impl Backend for NullBackend {
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate as f32
+    }
+
+    fn run(self: Box<Self>, mut synth: Synth, midi: MidiSender) -> Result<(), Box<dyn Error>> {
+        MidirListener::new(&self.client_name, midi).start();
+        let period = Duration::from_secs_f64(self.period_size as f64 / self.sample_rate as f64);
+        let mut left = vec![0.0; self.period_size];
+        let mut right = vec![0.0; self.period_size];
+        let mut deadline = Instant::now();
+        loop {
+            synth.render(&mut left, &mut right);
+            deadline += period;
+            if let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                thread::sleep(remaining);
+            }
+        }
+    }
+}
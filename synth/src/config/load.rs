@@ -1,8 +1,79 @@
-use super::Config;
+use super::{Config, DivisionConfig, SynthConfig};
+use std::collections::HashMap;
 use std::fs;
 
 pub fn load(file_path: &str) -> Result<Config, Box<dyn std::error::Error>> {
     let content = fs::read_to_string(file_path)?;
     let config: Config = toml::from_str(&content)?;
+    check_controllers(&config.synth)?;
     Ok(config)
 }
+
+/// Fails if two things respond to the same control change on a channel; the
+/// synth would only ever reach one of them.
+fn check_controllers(config: &SynthConfig) -> Result<(), String> {
+    let mut shared = vec![
+        (0, "Bank Select".to_string()),
+        (32, "Bank Select LSB".to_string()),
+    ];
+    shared.extend((120..=127).map(|cc| (cc, "a channel mode message".to_string())));
+    shared.extend(config.tuning.cc.map(|cc| (cc, "the tuning".to_string())));
+    let mut presets: Vec<_> = config.presets.iter().collect();
+    presets.sort_by_key(|(name, _)| *name);
+    shared.extend(
+        presets
+            .into_iter()
+            .map(|(name, preset)| (preset.midi_identifier, format!("preset {:?}", name))),
+    );
+    let mut stops: Vec<_> = config.stops.iter().collect();
+    stops.sort_by_key(|(name, _)| *name);
+    shared.extend(
+        stops
+            .into_iter()
+            .filter_map(|(name, stop)| Some((stop.midi_identifier?, format!("stop {:?}", name)))),
+    );
+
+    check_channel(&shared, None)?;
+    for division in &config.divisions {
+        let mut controllers = shared.clone();
+        controllers.extend(division_controllers(division));
+        check_channel(&controllers, Some(division.midi_channel))?;
+    }
+    Ok(())
+}
+
+fn division_controllers(division: &DivisionConfig) -> Vec<(u8, String)> {
+    let mut controllers = Vec::new();
+    if let Some(tremulant) = &division.tremulant {
+        controllers.extend(tremulant.toggle_cc.map(|cc| (cc, "the tremulant toggle")));
+        controllers.extend(tremulant.speed_cc.map(|cc| (cc, "the tremulant speed")));
+        controllers.extend(tremulant.depth_cc.map(|cc| (cc, "the tremulant depth")));
+    }
+    if let Some(wind) = &division.wind {
+        controllers.extend(wind.pressure_cc.map(|cc| (cc, "the wind pressure")));
+    }
+    if let Some(swell) = &division.swell {
+        controllers.push((swell.cc, "the swell pedal"));
+    }
+    controllers
+        .into_iter()
+        .map(|(cc, owner)| (cc, owner.to_string()))
+        .collect()
+}
+
+fn check_channel(controllers: &[(u8, String)], channel: Option<u8>) -> Result<(), String> {
+    let mut owners: HashMap<u8, &str> = HashMap::new();
+    for (cc, owner) in controllers {
+        if let Some(other) = owners.insert(*cc, owner) {
+            let place = match channel {
+                Some(channel) => format!(" on channel {}", channel),
+                None => String::new(),
+            };
+            return Err(format!(
+                "CC {}{} is assigned to both {} and {}",
+                cc, place, other, owner
+            ));
+        }
+    }
+    Ok(())
+}
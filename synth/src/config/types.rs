@@ -3,45 +3,229 @@ use std::collections::HashMap;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
-    pub jack: JackConfig,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    pub jack: Option<JackConfig>,
+    #[serde(default)]
+    pub alsa: AlsaConfig,
+    #[serde(default)]
+    pub null: NullConfig,
     pub synth: SynthConfig,
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct AudioConfig {
+    #[serde(default)]
+    pub backend: BackendKind,
+}
+
+#[derive(Debug, Default, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Jack,
+    Alsa,
+    Null,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SynthConfig {
     pub stops: HashMap<String, StopConfig>,
     pub presets: HashMap<String, PresetConfig>,
     pub preset_defaults: Vec<PresetDefaultConfig>,
+    #[serde(default)]
+    pub divisions: Vec<DivisionConfig>,
+    #[serde(default)]
+    pub master: MasterConfig,
+    #[serde(default)]
+    pub tuning: TuningConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TuningConfig {
+    pub reference_pitch: f32,
+    pub temperament: String,
+    pub scala: Option<String>,
+    pub keyboard_map: Option<String>,
+    pub cc: Option<u8>,
+    pub choices: Vec<String>,
+}
+
+impl Default for TuningConfig {
+    fn default() -> Self {
+        Self {
+            reference_pitch: 440.0,
+            temperament: "equal".to_string(),
+            scala: None,
+            keyboard_map: None,
+            cc: None,
+            choices: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct JackConfig {
     pub client_name: String,
-    pub audio_out_port_name: String,
+    pub audio_out_l_port_name: String,
+    pub audio_out_r_port_name: String,
     pub midi_in_port_name: String,
     pub system_audio_l_port_name: String,
     pub system_audio_r_port_name: String,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AlsaConfig {
+    pub client_name: String,
+    pub device: String,
+    pub sample_rate: u32,
+    pub period_size: usize,
+}
+
+impl Default for AlsaConfig {
+    fn default() -> Self {
+        Self {
+            client_name: "Synth".to_string(),
+            device: "default".to_string(),
+            sample_rate: 48000,
+            period_size: 256,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NullConfig {
+    pub client_name: String,
+    pub sample_rate: u32,
+    pub period_size: usize,
+}
+
+impl Default for NullConfig {
+    fn default() -> Self {
+        Self {
+            client_name: "Synth".to_string(),
+            sample_rate: 48000,
+            period_size: 256,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PresetConfig {
     pub midi_identifier: u8,
     pub stops: Vec<PresetStopConfig>,
+    /// 1-based MIDI channels of the divisions the preset is meant for; empty
+    /// means all of them.
+    #[serde(default)]
+    pub channels: Vec<u8>,
+    /// Program Change number (0-127, as sent) that recalls the preset.
+    pub program: Option<u8>,
+    /// Bank the program is in: CC0 * 128 + CC32.
+    #[serde(default)]
+    pub bank: u16,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum PresetStopConfig {
     Named(String),
-    Inline(StopConfig),
+    Inline(Box<StopConfig>),
 }
 
 #[derive(Debug, Deserialize)]
 pub struct StopConfig {
     pub midi_identifier: Option<u8>,
+    #[serde(default)]
     pub waveform: String,
+    pub harmonics: Option<Vec<f32>>,
+    pub phases: Option<Vec<f32>>,
+    pub antialiasing: Option<String>,
     pub frequency_ratio: f32,
     pub amplitude_ratio: f32,
+    pub envelope: Option<EnvelopeConfig>,
+    pub chiff: Option<ChiffConfig>,
+    pub samples: Option<SamplesConfig>,
+    #[serde(default)]
+    pub detune: f32,
+    #[serde(default)]
+    pub detune_paired: bool,
+    #[serde(default)]
+    pub random_detune: f32,
+    #[serde(default)]
+    pub pan: f32,
+    pub pan_layout: Option<String>,
+    #[serde(default = "default_pan_spread")]
+    pub pan_spread: f32,
+    pub composition: Option<Vec<BreakConfig>>,
+    pub compass: Option<CompassConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompassConfig {
+    pub lowest: u8,
+    pub highest: u8,
+    #[serde(default)]
+    pub outside: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BreakConfig {
+    #[serde(default)]
+    pub from: u8,
+    pub ratios: Vec<f32>,
+}
+
+fn default_pan_spread() -> f32 {
+    0.5
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SamplesConfig {
+    #[serde(default)]
+    pub directory: String,
+    /// Reads each file in the background from its first note instead of at
+    /// startup.
+    #[serde(default)]
+    pub lazy_load: bool,
+    pub notes: Vec<SampleNoteConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SampleNoteConfig {
+    pub key: u8,
+    pub file: String,
+    pub pitch: Option<f32>,
+    pub loop_start: Option<usize>,
+    pub loop_end: Option<usize>,
+    pub release: Option<String>,
+    pub release_start: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EnvelopeConfig {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+    pub curve: String,
+    pub pitch_scaling: f32,
+}
+
+impl Default for EnvelopeConfig {
+    fn default() -> Self {
+        Self {
+            attack: 0.05,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.1,
+            curve: "linear".to_string(),
+            pitch_scaling: 0.0,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -49,3 +233,170 @@ pub struct PresetDefaultConfig {
     pub midi_channel: u8,
     pub preset_name: String,
 }
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ChiffConfig {
+    pub level: f32,
+    pub duration: f32,
+    pub harmonic: f32,
+    pub q: f32,
+    pub pitch_offset: f32,
+    pub overshoot: f32,
+    pub settle_time: f32,
+}
+
+impl Default for ChiffConfig {
+    fn default() -> Self {
+        Self {
+            level: 0.3,
+            duration: 0.04,
+            harmonic: 3.0,
+            q: 2.0,
+            pitch_offset: 15.0,
+            overshoot: 0.1,
+            settle_time: 0.06,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DivisionConfig {
+    pub midi_channel: u8,
+    pub tremulant: Option<TremulantConfig>,
+    pub wind: Option<WindConfig>,
+    pub swell: Option<SwellConfig>,
+    pub effects: Option<Vec<EffectConfig>>,
+    pub compass: Option<CompassConfig>,
+    /// Output level in dB.
+    pub gain: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MasterConfig {
+    #[serde(default)]
+    pub effects: Vec<EffectConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EffectConfig {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub bypass: bool,
+    pub frequency: f32,
+    pub q: f32,
+    pub gain: f32,
+    pub mode: String,
+    pub delay: f32,
+    pub feedback: f32,
+    pub mix: f32,
+    pub rt60: f32,
+    pub pre_delay: f32,
+    pub damping: f32,
+    pub size: f32,
+    pub width: f32,
+    pub path: String,
+}
+
+impl Default for EffectConfig {
+    fn default() -> Self {
+        Self {
+            kind: String::new(),
+            bypass: false,
+            frequency: 1000.0,
+            q: std::f32::consts::FRAC_1_SQRT_2,
+            gain: 0.0,
+            mode: "low_pass".to_string(),
+            delay: 100.0,
+            feedback: 0.4,
+            mix: 0.3,
+            rt60: 2.5,
+            pre_delay: 20.0,
+            damping: 0.4,
+            size: 1.0,
+            width: 1.0,
+            path: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TremulantConfig {
+    pub enabled: bool,
+    pub speed: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    pub amplitude_depth: f32,
+    pub pitch_depth: f32,
+    pub toggle_cc: Option<u8>,
+    pub speed_cc: Option<u8>,
+    pub depth_cc: Option<u8>,
+}
+
+impl Default for TremulantConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            speed: 5.5,
+            min_speed: 3.0,
+            max_speed: 9.0,
+            amplitude_depth: 0.15,
+            pitch_depth: 6.0,
+            toggle_cc: None,
+            speed_cc: None,
+            depth_cc: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WindConfig {
+    pub sag: f32,
+    pub capacity: f32,
+    pub response: f32,
+    pub recovery: f32,
+    pub pitch_depth: f32,
+    pub amplitude_depth: f32,
+    pub pressure_cc: Option<u8>,
+}
+
+impl Default for WindConfig {
+    fn default() -> Self {
+        Self {
+            sag: 0.15,
+            capacity: 8.0,
+            response: 0.03,
+            recovery: 0.2,
+            pitch_depth: 40.0,
+            amplitude_depth: 0.5,
+            pressure_cc: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SwellConfig {
+    pub cc: u8,
+    pub initial: f32,
+    pub closed_attenuation: f32,
+    pub closed_cutoff: f32,
+    pub open_cutoff: f32,
+    pub smoothing: f32,
+}
+
+impl Default for SwellConfig {
+    fn default() -> Self {
+        Self {
+            cc: 11,
+            initial: 1.0,
+            closed_attenuation: 24.0,
+            closed_cutoff: 1500.0,
+            open_cutoff: 18000.0,
+            smoothing: 0.05,
+        }
+    }
+}
@@ -1,51 +1,356 @@
-#[derive(Debug, Copy, Clone)]
-pub enum MessageKind {
-    NoteOn,
-    NoteOff,
-    ControlChange,
-    ProgramChange,
-}
+/// Longest SysEx kept, in bytes between 0xF0 and 0xF7. Longer ones are
+/// dropped rather than buffered without bound.
+const MAX_SYSEX_LENGTH: usize = 4096;
 
-#[derive(Debug, Copy, Clone)]
-pub struct Message {
-    pub channel: u8,
-    pub kind: MessageKind,
-    pub identifier: u8,
-    pub value: u8,
+/// A complete MIDI 1.0 message. Channels are 0-based; all other values are
+/// as on the wire.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    NoteOff {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    /// A velocity of 0 means note off.
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    PolyPressure {
+        channel: u8,
+        key: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    /// -8192 to 8191, centred on 0.
+    PitchBend {
+        channel: u8,
+        value: i16,
+    },
+    /// The bytes between 0xF0 and 0xF7, including the manufacturer ID.
+    SysEx(Vec<u8>),
+    /// MTC quarter frame: which piece of the time code, and its value.
+    TimeCodeQuarterFrame {
+        piece: u8,
+        value: u8,
+    },
+    /// In MIDI beats (sixteenth notes) since the start of the song.
+    SongPosition(u16),
+    SongSelect(u8),
+    TuneRequest,
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
 }
 
 impl Message {
-    pub fn frequency(&self) -> Result<f32, String> {
-        match self.kind {
-            MessageKind::NoteOn => Ok(to_freq(self.identifier)),
-            MessageKind::NoteOff => Ok(to_freq(self.identifier)),
-            _ => Err(format!(
-                "MIDI message type has no frequency: {:?}",
-                self.kind
-            )),
+    /// The channel of a channel message.
+    pub fn channel(&self) -> Option<u8> {
+        match *self {
+            Message::NoteOff { channel, .. }
+            | Message::NoteOn { channel, .. }
+            | Message::PolyPressure { channel, .. }
+            | Message::ControlChange { channel, .. }
+            | Message::ProgramChange { channel, .. }
+            | Message::ChannelPressure { channel, .. }
+            | Message::PitchBend { channel, .. } => Some(channel),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a system real-time message, which may arrive in the
+    /// middle of any other.
+    pub fn is_real_time(&self) -> bool {
+        matches!(
+            self,
+            Message::Clock
+                | Message::Start
+                | Message::Continue
+                | Message::Stop
+                | Message::ActiveSensing
+                | Message::Reset
+        )
+    }
+
+    /// Builds a channel or system common message from its status byte and
+    /// `data_length(status)` data bytes.
+    fn from_parts(status: u8, data: &[u8]) -> Option<Self> {
+        let channel = status & 0x0F;
+        let message = match status & 0xF0 {
+            0x80 => Message::NoteOff {
+                channel,
+                key: data[0],
+                velocity: data[1],
+            },
+            0x90 => Message::NoteOn {
+                channel,
+                key: data[0],
+                velocity: data[1],
+            },
+            0xA0 => Message::PolyPressure {
+                channel,
+                key: data[0],
+                pressure: data[1],
+            },
+            0xB0 => Message::ControlChange {
+                channel,
+                controller: data[0],
+                value: data[1],
+            },
+            0xC0 => Message::ProgramChange {
+                channel,
+                program: data[0],
+            },
+            0xD0 => Message::ChannelPressure {
+                channel,
+                pressure: data[0],
+            },
+            0xE0 => Message::PitchBend {
+                channel,
+                value: fourteen_bit(data) as i16 - 8192,
+            },
+            _ => match status {
+                0xF1 => Message::TimeCodeQuarterFrame {
+                    piece: data[0] >> 4,
+                    value: data[0] & 0x0F,
+                },
+                0xF2 => Message::SongPosition(fourteen_bit(data)),
+                0xF3 => Message::SongSelect(data[0]),
+                0xF6 => Message::TuneRequest,
+                _ => return None,
+            },
+        };
+        Some(message)
+    }
+
+    fn real_time(status: u8) -> Option<Self> {
+        match status {
+            0xF8 => Some(Message::Clock),
+            0xFA => Some(Message::Start),
+            0xFB => Some(Message::Continue),
+            0xFC => Some(Message::Stop),
+            0xFE => Some(Message::ActiveSensing),
+            0xFF => Some(Message::Reset),
+            _ => None,
         }
     }
 }
 
-pub fn try_parse(data: &[u8; 3]) -> Result<Message, String> {
-    let [status, identifier, value] = *data;
-    let raw_kind = status & 0xF0;
-    let kind = match raw_kind {
-        0x80 => MessageKind::NoteOff,
-        0x90 => MessageKind::NoteOn,
-        0xB0 => MessageKind::ControlChange,
-        0xC0 => MessageKind::ProgramChange,
-        _ => return Err(format!("Unsupported MIDI message type: {:#X}", raw_kind)),
-    };
-    let channel = status & 0x0F;
-    Ok(Message {
-        channel,
-        kind,
-        identifier,
-        value,
-    })
+/// Data bytes following `status`, for all but SysEx.
+fn data_length(status: u8) -> usize {
+    match status & 0xF0 {
+        0xC0 | 0xD0 => 1,
+        0x80..=0xE0 => 2,
+        _ => match status {
+            0xF1 | 0xF3 => 1,
+            0xF2 => 2,
+            _ => 0,
+        },
+    }
+}
+
+/// Least significant 7 bits first, as pitch bend and song position send them.
+fn fourteen_bit(data: &[u8]) -> u16 {
+    data[0] as u16 | (data[1] as u16) << 7
+}
+
+/// Turns a MIDI byte stream into messages. Handles running status, system
+/// real-time bytes interleaved anywhere, and SysEx of any length, so bytes
+/// may be fed in whatever pieces they arrive in.
+#[derive(Debug, Default)]
+pub struct Parser {
+    /// The status the next data bytes belong to. Persists after channel
+    /// messages, which is what running status relies on.
+    status: Option<u8>,
+    data: [u8; 2],
+    length: usize,
+    /// The SysEx being received, if any.
+    sysex: Option<Vec<u8>>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds one byte, returning the message it completes, if any. Data
+    /// bytes without a status to belong to are ignored.
+    pub fn push(&mut self, byte: u8) -> Option<Message> {
+        if byte >= 0xF8 {
+            return Message::real_time(byte);
+        }
+        if byte & 0x80 == 0 {
+            return self.push_data(byte);
+        }
+        // Any other status ends a SysEx. One cut short this way is dropped,
+        // as it is most likely incomplete.
+        let sysex = self.sysex.take();
+        self.length = 0;
+        self.status = None;
+        match byte {
+            0xF0 => self.sysex = Some(Vec::new()),
+            0xF7 => return sysex.map(Message::SysEx),
+            _ if data_length(byte) == 0 => return Message::from_parts(byte, &[]),
+            _ => self.status = Some(byte),
+        }
+        None
+    }
+
+    fn push_data(&mut self, byte: u8) -> Option<Message> {
+        if let Some(sysex) = &mut self.sysex {
+            if sysex.len() < MAX_SYSEX_LENGTH {
+                sysex.push(byte);
+            } else {
+                // The rest is ignored as data without a status.
+                self.sysex = None;
+            }
+            return None;
+        }
+        let status = self.status?;
+        self.data[self.length] = byte;
+        self.length += 1;
+        if self.length < data_length(status) {
+            return None;
+        }
+        self.length = 0;
+        // System common messages cancel running status.
+        if status >= 0xF0 {
+            self.status = None;
+        }
+        Message::from_parts(status, &self.data)
+    }
 }
 
-fn to_freq(identifier: u8) -> f32 {
-    440.0 * (2.0f32).powf((identifier as f32 - 69.0) / 12.0)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<Message> {
+        let mut parser = Parser::new();
+        bytes.iter().filter_map(|&byte| parser.push(byte)).collect()
+    }
+
+    fn note_on(key: u8, velocity: u8) -> Message {
+        Message::NoteOn {
+            channel: 2,
+            key,
+            velocity,
+        }
+    }
+
+    #[test]
+    fn running_status_covers_note_on_and_off() {
+        assert_eq!(
+            parse(&[0x92, 60, 100, 64, 90, 60, 0, 0x82, 64, 10, 67, 20]),
+            vec![
+                note_on(60, 100),
+                note_on(64, 90),
+                note_on(60, 0),
+                Message::NoteOff {
+                    channel: 2,
+                    key: 64,
+                    velocity: 10,
+                },
+                Message::NoteOff {
+                    channel: 2,
+                    key: 67,
+                    velocity: 20,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn real_time_bytes_pass_through_a_message() {
+        assert_eq!(
+            parse(&[0x92, 0xF8, 60, 0xFE, 100, 64, 0xFF, 90]),
+            vec![
+                Message::Clock,
+                Message::ActiveSensing,
+                note_on(60, 100),
+                Message::Reset,
+                note_on(64, 90),
+            ]
+        );
+    }
+
+    #[test]
+    fn system_common_cancels_running_status() {
+        assert_eq!(
+            parse(&[0x92, 60, 100, 0xF3, 5, 64, 90]),
+            vec![note_on(60, 100), Message::SongSelect(5)]
+        );
+    }
+
+    #[test]
+    fn sysex_ends_at_f7_and_is_dropped_when_interrupted() {
+        assert_eq!(
+            parse(&[0xF0, 0x7E, 0x7F, 0xF8, 0x09, 0xF7]),
+            vec![Message::Clock, Message::SysEx(vec![0x7E, 0x7F, 0x09])]
+        );
+        assert_eq!(
+            parse(&[0xF0, 0x7E, 0x7F, 0x92, 60, 100, 0xF7]),
+            vec![note_on(60, 100)]
+        );
+    }
+
+    #[test]
+    fn overlong_sysex_is_dropped() {
+        let mut bytes = vec![0xF0];
+        bytes.extend(std::iter::repeat_n(0x01, MAX_SYSEX_LENGTH + 1));
+        bytes.extend([0xF7, 0x92, 60, 100]);
+        assert_eq!(parse(&bytes), vec![note_on(60, 100)]);
+
+        let mut bytes = vec![0xF0];
+        bytes.extend(std::iter::repeat_n(0x01, MAX_SYSEX_LENGTH));
+        bytes.push(0xF7);
+        assert_eq!(
+            parse(&bytes),
+            vec![Message::SysEx(vec![0x01; MAX_SYSEX_LENGTH])]
+        );
+    }
+
+    #[test]
+    fn stray_data_bytes_are_ignored() {
+        assert_eq!(parse(&[60, 100, 0x92, 60, 100]), vec![note_on(60, 100)]);
+        assert_eq!(parse(&[0xF6, 60, 100]), vec![Message::TuneRequest]);
+        assert_eq!(parse(&[0xF7, 60]), vec![]);
+    }
+
+    #[test]
+    fn pitch_bend_is_centred_on_zero() {
+        assert_eq!(
+            parse(&[0xE0, 0x00, 0x40, 0x7F, 0x7F, 0x00, 0x00]),
+            vec![
+                Message::PitchBend {
+                    channel: 0,
+                    value: 0,
+                },
+                Message::PitchBend {
+                    channel: 0,
+                    value: 8191,
+                },
+                Message::PitchBend {
+                    channel: 0,
+                    value: -8192,
+                },
+            ]
+        );
+    }
 }
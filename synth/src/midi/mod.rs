@@ -1,4 +1,2 @@
-pub mod listener;
 mod message;
-pub use listener::MidiListener;
 pub use message::*;
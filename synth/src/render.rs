@@ -0,0 +1,207 @@
+use crate::config::SynthConfig;
+use crate::synth::{Controller, Synth};
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use std::error::Error;
+use std::fs;
+
+const USAGE: &str = "Usage: organsynth render <input.mid> <output.wav> [--sample-rate HZ] [--tail SECONDS] [--tuning NAME]";
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+const DEFAULT_TAIL_SECONDS: f32 = 3.0;
+const DEFAULT_MICROS_PER_BEAT: f64 = 500_000.0;
+const RENDER_BLOCK_SIZE: usize = 1024;
+
+pub struct RenderOptions {
+    pub input_path: String,
+    pub output_path: String,
+    pub sample_rate: u32,
+    pub tail_seconds: f32,
+    /// Overrides the configured tuning, e.g. "meantone".
+    pub tuning: Option<String>,
+}
+
+impl RenderOptions {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut sample_rate = DEFAULT_SAMPLE_RATE;
+        let mut tail_seconds = DEFAULT_TAIL_SECONDS;
+        let mut tuning = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sample-rate" => {
+                    sample_rate = parse_value(arg, args.next())?;
+                }
+                "--tail" => {
+                    tail_seconds = parse_value(arg, args.next())?;
+                }
+                "--tuning" => {
+                    tuning = Some(parse_value(arg, args.next())?);
+                }
+                _ => positional.push(arg.clone()),
+            }
+        }
+        let [input_path, output_path]: [String; 2] =
+            positional.try_into().map_err(|_| USAGE.to_string())?;
+        Ok(Self {
+            input_path,
+            output_path,
+            sample_rate,
+            tail_seconds,
+            tuning,
+        })
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("Invalid or missing value for {}\n{}", flag, USAGE))
+}
+
+/// A channel or SysEx message from the MIDI file, scheduled at an absolute
+/// sample.
+struct TimedEvent {
+    frame: u64,
+    midi: Vec<u8>,
+}
+
+/// Renders a Standard MIDI File to a WAV file without any audio server,
+/// driving the synth block by block.
+pub fn render(config: SynthConfig, options: &RenderOptions) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(&options.input_path)?;
+    let smf = Smf::parse(&bytes)?;
+    let events = schedule_events(&smf, options.sample_rate);
+
+    let (mut synth, mut controller) = Synth::new_offline(options.sample_rate as f32, config);
+    if let Some(tuning) = &options.tuning {
+        controller.set_tuning(tuning)?;
+    }
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: options.sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&options.output_path, spec)?;
+
+    let mut frame: u64 = 0;
+    for event in events.iter() {
+        let frames = event.frame.saturating_sub(frame);
+        render_frames(&mut synth, &mut controller, &mut writer, frames)?;
+        frame = frame.max(event.frame);
+        controller.handle_midi(event.frame, &event.midi);
+    }
+    let tail_frames = (options.tail_seconds.max(0.0) * options.sample_rate as f32) as u64;
+    render_frames(&mut synth, &mut controller, &mut writer, tail_frames)?;
+    frame += tail_frames;
+    writer.finalize()?;
+
+    println!(
+        "Rendered {} events ({:.2}s) to {}",
+        events.len(),
+        frame as f64 / options.sample_rate as f64,
+        options.output_path
+    );
+    Ok(())
+}
+
+/// Renders `frames` frames in blocks and appends them to the WAV file.
+fn render_frames<W: std::io::Write + std::io::Seek>(
+    synth: &mut Synth,
+    controller: &mut Controller,
+    writer: &mut hound::WavWriter<W>,
+    frames: u64,
+) -> Result<(), hound::Error> {
+    let mut left = [0.0; RENDER_BLOCK_SIZE];
+    let mut right = [0.0; RENDER_BLOCK_SIZE];
+    let mut remaining = frames;
+    while remaining > 0 {
+        let len = (remaining as usize).min(RENDER_BLOCK_SIZE);
+        synth.render(&mut left[..len], &mut right[..len]);
+        for (left, right) in left[..len].iter().zip(&right[..len]) {
+            writer.write_sample(*left)?;
+            writer.write_sample(*right)?;
+        }
+        controller.collect();
+        remaining -= len as u64;
+    }
+    Ok(())
+}
+
+/// Flattens all tracks into one list of channel messages ordered by time,
+/// converting ticks to samples through the file's tempo map.
+fn schedule_events(smf: &Smf, sample_rate: u32) -> Vec<TimedEvent> {
+    // (absolute tick, track index, event)
+    let mut ticked = Vec::new();
+    let mut track_offset: u64 = 0;
+    for (track_index, track) in smf.tracks.iter().enumerate() {
+        let mut tick = track_offset;
+        for event in track.iter() {
+            tick += event.delta.as_int() as u64;
+            ticked.push((tick, track_index, event.kind));
+        }
+        if let midly::Format::Sequential = smf.header.format {
+            track_offset = tick;
+        }
+    }
+    // Stable sort keeps per-track ordering for events on the same tick.
+    ticked.sort_by_key(|(tick, _, _)| *tick);
+
+    let mut events = Vec::new();
+    let mut micros_per_beat = DEFAULT_MICROS_PER_BEAT;
+    let mut last_tick: u64 = 0;
+    let mut seconds = 0.0f64;
+    for (tick, _, kind) in ticked {
+        seconds +=
+            (tick - last_tick) as f64 * seconds_per_tick(&smf.header.timing, micros_per_beat);
+        last_tick = tick;
+        match kind {
+            TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                micros_per_beat = tempo.as_int() as f64;
+            }
+            TrackEventKind::Midi { channel, message } => {
+                events.push(TimedEvent {
+                    frame: (seconds * sample_rate as f64).round() as u64,
+                    midi: to_raw_midi(channel.as_int(), message),
+                });
+            }
+            // Stored without the leading 0xF0.
+            TrackEventKind::SysEx(data) => {
+                events.push(TimedEvent {
+                    frame: (seconds * sample_rate as f64).round() as u64,
+                    midi: [&[0xF0], data].concat(),
+                });
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+fn seconds_per_tick(timing: &Timing, micros_per_beat: f64) -> f64 {
+    match timing {
+        Timing::Metrical(ticks_per_beat) => {
+            micros_per_beat / 1_000_000.0 / ticks_per_beat.as_int() as f64
+        }
+        Timing::Timecode(fps, ticks_per_frame) => {
+            1.0 / (fps.as_f32() as f64 * *ticks_per_frame as f64)
+        }
+    }
+}
+
+fn to_raw_midi(channel: u8, message: MidiMessage) -> Vec<u8> {
+    match message {
+        MidiMessage::NoteOff { key, vel } => vec![0x80 | channel, key.as_int(), vel.as_int()],
+        MidiMessage::NoteOn { key, vel } => vec![0x90 | channel, key.as_int(), vel.as_int()],
+        MidiMessage::Aftertouch { key, vel } => vec![0xA0 | channel, key.as_int(), vel.as_int()],
+        MidiMessage::Controller { controller, value } => {
+            vec![0xB0 | channel, controller.as_int(), value.as_int()]
+        }
+        MidiMessage::ProgramChange { program } => vec![0xC0 | channel, program.as_int()],
+        MidiMessage::ChannelAftertouch { vel } => vec![0xD0 | channel, vel.as_int()],
+        MidiMessage::PitchBend { bend } => {
+            let bend = bend.0.as_int();
+            vec![0xE0 | channel, (bend & 0x7F) as u8, (bend >> 7) as u8]
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::f32::consts::TAU;
+use std::fmt;
+
+const TABLE_SIZE: usize = 2048;
+
+/// A single-cycle wavetable built from a harmonic spectrum.
+///
+/// The table is stored at several band limits (all harmonics, then half of
+/// them, a quarter, ...) so high notes can drop the partials that would
+/// otherwise alias above Nyquist.
+pub struct HarmonicTable {
+    harmonics: Vec<f32>,
+    phases: Vec<f32>,
+    levels: Vec<Level>,
+}
+
+struct Level {
+    harmonic_count: usize,
+    // One extra sample wraps around to the start to simplify interpolation.
+    table: Vec<f32>,
+}
+
+impl HarmonicTable {
+    /// `harmonics[n]` is the amplitude of partial n + 1; `phases[n]` is its
+    /// starting phase in cycles and defaults to 0.
+    pub fn new(harmonics: &[f32], phases: &[f32]) -> Self {
+        let mut levels = Vec::new();
+        let mut harmonic_count = harmonics.len().max(1);
+        loop {
+            levels.push(Level {
+                harmonic_count,
+                table: build_table(&harmonics[..harmonic_count.min(harmonics.len())], phases),
+            });
+            if harmonic_count == 1 {
+                break;
+            }
+            harmonic_count = harmonic_count.div_ceil(2);
+        }
+        // Normalize every level by the full spectrum's peak so dropping
+        // partials at the top of the compass doesn't change the loudness.
+        let peak = levels[0]
+            .table
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        if peak > 0.0 {
+            for level in levels.iter_mut() {
+                level.table.iter_mut().for_each(|sample| *sample /= peak);
+            }
+        }
+        Self {
+            harmonics: harmonics.to_vec(),
+            phases: phases.to_vec(),
+            levels,
+        }
+    }
+
+    pub fn sample(&self, phase: f32, phase_increment: f32) -> f32 {
+        let level = self
+            .levels
+            .iter()
+            .find(|level| level.harmonic_count as f32 * phase_increment < 0.5)
+            .unwrap_or(&self.levels[self.levels.len() - 1]);
+        let position = phase * TABLE_SIZE as f32;
+        let index = (position as usize).min(TABLE_SIZE - 1);
+        let fraction = position - index as f32;
+        let a = level.table[index];
+        let b = level.table[index + 1];
+        a + (b - a) * fraction
+    }
+}
+
+impl fmt::Debug for HarmonicTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HarmonicTable")
+            .field("harmonics", &self.harmonics)
+            .field("phases", &self.phases)
+            .finish()
+    }
+}
+
+fn build_table(harmonics: &[f32], phases: &[f32]) -> Vec<f32> {
+    (0..=TABLE_SIZE)
+        .map(|i| {
+            let x = i as f32 / TABLE_SIZE as f32;
+            harmonics
+                .iter()
+                .enumerate()
+                .map(|(n, amplitude)| {
+                    let phase = phases.get(n).copied().unwrap_or(0.0);
+                    amplitude * (TAU * ((n + 1) as f32 * x + phase)).sin()
+                })
+                .sum()
+        })
+        .collect()
+}
@@ -0,0 +1,51 @@
+use crate::config::CompassConfig;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutsideCompass {
+    Silent,
+    /// Repeat the nearest octave inside the compass, as short-compass ranks
+    /// and octave couplers running off the end of a chest do.
+    Fold,
+}
+
+/// The range of keys a stop or division has pipes or keys for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Compass {
+    pub lowest: u8,
+    pub highest: u8,
+    pub outside: OutsideCompass,
+}
+
+impl Compass {
+    pub fn new(config: &CompassConfig) -> Self {
+        Self {
+            lowest: config.lowest.min(config.highest),
+            highest: config.highest.max(config.lowest),
+            outside: match config.outside.as_str() {
+                "fold" => OutsideCompass::Fold,
+                _ => OutsideCompass::Silent,
+            },
+        }
+    }
+
+    /// The key that sounds when `key` is played, or `None` if it is silent.
+    pub fn map(&self, key: u8) -> Option<u8> {
+        if (self.lowest..=self.highest).contains(&key) {
+            return Some(key);
+        }
+        match self.outside {
+            OutsideCompass::Silent => None,
+            OutsideCompass::Fold => {
+                let mut key = key as i32;
+                while key < self.lowest as i32 {
+                    key += 12;
+                }
+                while key > self.highest as i32 {
+                    key -= 12;
+                }
+                // A compass narrower than an octave can't hold every note.
+                (key >= self.lowest as i32).then_some(key as u8)
+            }
+        }
+    }
+}
@@ -1,56 +1,148 @@
-use super::Stop;
+use super::tuning::Tuning;
+use super::{Division, Stop};
 use crate::config::SynthConfig;
 use std::collections::HashMap;
 
+/// Every configured stop, built once so that presets and stop controls
+/// referring to the same name share its id.
+pub fn get_named_stops(config: &SynthConfig) -> HashMap<String, Stop> {
+    config
+        .stops
+        .iter()
+        .map(|(name, stop_config)| (name.clone(), Stop::new(stop_config)))
+        .collect()
+}
+
 pub fn get_stop(
     preset_stop_config: &crate::config::PresetStopConfig,
-    config: &SynthConfig,
+    named_stops: &HashMap<String, Stop>,
 ) -> Stop {
     match preset_stop_config {
-        crate::config::PresetStopConfig::Named(name) => Stop::new(&config.stops[name]),
+        crate::config::PresetStopConfig::Named(name) => named_stops[name].clone(),
         crate::config::PresetStopConfig::Inline(stop) => Stop::new(stop),
     }
 }
 
-pub fn get_preset(preset_config: &crate::config::PresetConfig, config: &SynthConfig) -> Vec<Stop> {
+pub fn get_preset(
+    preset_config: &crate::config::PresetConfig,
+    named_stops: &HashMap<String, Stop>,
+) -> Vec<Stop> {
     preset_config
         .stops
         .iter()
-        .map(|stop| get_stop(stop, config))
+        .map(|stop| get_stop(stop, named_stops))
         .collect()
 }
 
-pub fn get_preset_defaults(config: &SynthConfig) -> HashMap<u8, Vec<Stop>> {
+pub fn get_preset_defaults(
+    config: &SynthConfig,
+    named_stops: &HashMap<String, Stop>,
+) -> HashMap<u8, Vec<Stop>> {
     config
         .preset_defaults
         .iter()
         .map(|preset_default| {
             (
                 preset_default.midi_channel,
-                get_preset(&config.presets[&preset_default.preset_name], config),
+                get_preset(&config.presets[&preset_default.preset_name], named_stops),
             )
         })
         .collect()
 }
 
-pub fn get_stops(config: &SynthConfig) -> HashMap<u8, Stop> {
+pub fn get_stops(config: &SynthConfig, named_stops: &HashMap<String, Stop>) -> HashMap<u8, Stop> {
     config
         .stops
-        .values()
-        .filter(|stop_config| stop_config.midi_identifier.is_some())
-        .map(|stop_config| (stop_config.midi_identifier.unwrap(), Stop::new(stop_config)))
+        .iter()
+        .filter_map(|(name, stop_config)| {
+            Some((stop_config.midi_identifier?, named_stops[name].clone()))
+        })
         .collect()
 }
 
-pub fn get_presets(config: &SynthConfig) -> HashMap<u8, Vec<Stop>> {
+pub fn get_presets(
+    config: &SynthConfig,
+    named_stops: &HashMap<String, Stop>,
+) -> HashMap<u8, Vec<Stop>> {
     config
         .presets
         .values()
         .map(|preset_config| {
             (
                 preset_config.midi_identifier,
-                get_preset(preset_config, config),
+                get_preset(preset_config, named_stops),
             )
         })
         .collect()
 }
+
+/// A preset recalled by Program Change on one of `channels` (1-based; all
+/// if empty) while `bank` is selected.
+pub struct ProgramPreset {
+    pub name: String,
+    pub channels: Vec<u8>,
+    pub bank: u16,
+    pub program: u8,
+    pub stops: Vec<Stop>,
+}
+
+pub fn get_program_presets(
+    config: &SynthConfig,
+    named_stops: &HashMap<String, Stop>,
+) -> Vec<ProgramPreset> {
+    config
+        .presets
+        .iter()
+        .filter_map(|(name, preset_config)| {
+            Some(ProgramPreset {
+                name: name.clone(),
+                channels: preset_config.channels.clone(),
+                bank: preset_config.bank,
+                program: preset_config.program?,
+                stops: get_preset(preset_config, named_stops),
+            })
+        })
+        .collect()
+}
+
+pub fn get_divisions(config: &SynthConfig) -> HashMap<u8, Division> {
+    config
+        .divisions
+        .iter()
+        .map(|division_config| (division_config.midi_channel, Division::new(division_config)))
+        .collect()
+}
+
+/// The tunings selectable by the tuning CC, in controller order. The
+/// configured one is always available, even if not listed.
+pub fn get_tunings(config: &SynthConfig) -> (Tuning, Vec<Tuning>) {
+    let tuning_config = &config.tuning;
+    let initial = Tuning::new(tuning_config).unwrap_or_else(|e| {
+        println!("{}; using equal temperament", e);
+        Tuning::temperament("equal", tuning_config.reference_pitch).unwrap()
+    });
+    let mut choices: Vec<Tuning> = if tuning_config.choices.is_empty() {
+        Tuning::temperament_names()
+            .filter_map(|name| Tuning::temperament(name, tuning_config.reference_pitch))
+            .collect()
+    } else {
+        tuning_config
+            .choices
+            .iter()
+            .filter_map(|name| {
+                let tuning = match name.as_str() {
+                    "scala" => Some(initial.clone()).filter(|tuning| tuning.name == "scala"),
+                    _ => Tuning::temperament(name, tuning_config.reference_pitch),
+                };
+                if tuning.is_none() {
+                    println!("Unknown tuning choice: {:?}", name);
+                }
+                tuning
+            })
+            .collect()
+    };
+    if !choices.contains(&initial) {
+        choices.insert(0, initial.clone());
+    }
+    (initial, choices)
+}
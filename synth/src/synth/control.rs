@@ -0,0 +1,489 @@
+use super::config::{self, ProgramPreset};
+use super::note::Note;
+use super::oscillator::Oscillator;
+use super::queue::{Command, ControlQueues, Retired};
+use super::thingy::{InternalSynth, VOICES_PER_DIVISION};
+use super::tuning::Tuning;
+use super::{Division, Stop};
+use crate::config::SynthConfig;
+use crate::midi;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::collections::HashMap;
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+/// Room for pipes in every note, so drawing stops while it sounds does
+/// not allocate on the audio thread.
+const PIPES_PER_VOICE: usize = 64;
+/// Pipe vectors kept ready, enough for a full division.
+const POOL_SIZE: usize = VOICES_PER_DIVISION;
+const BANK_SELECT_MSB: u8 = 0;
+const BANK_SELECT_LSB: u8 = 32;
+const ALL_SOUND_OFF: u8 = 120;
+const RESET_ALL_CONTROLLERS: u8 = 121;
+const LOCAL_CONTROL: u8 = 122;
+/// In bytes, enough for a sizeable SysEx dump.
+const MIDI_CAPACITY: usize = 16384;
+/// How often an idle control thread collects what the audio thread retired.
+const COLLECT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Feeds raw MIDI to the control thread. Sending never blocks or
+/// allocates, so it is safe from an audio callback.
+pub struct MidiSender {
+    /// Raw bytes, each with the frame it belongs to, so messages of any
+    /// length and running status pass through unchanged.
+    queue: Producer<(u64, u8)>,
+    worker: Thread,
+}
+
+impl MidiSender {
+    /// Applies `bytes` as soon as possible.
+    pub fn send(&mut self, bytes: &[u8]) {
+        self.send_at(0, bytes);
+    }
+
+    /// Applies `bytes` when the synth reaches `frame` (see `Synth::frame`),
+    /// or at once if it is already past it. Drops bytes if the control
+    /// thread is hopelessly behind; the parser resynchronizes on the next
+    /// status byte.
+    pub fn send_at(&mut self, frame: u64, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.queue.push((frame, byte)).is_err() {
+                break;
+            }
+        }
+        self.worker.unpark();
+    }
+}
+
+/// Everything that turns MIDI into commands for the audio thread: stops,
+/// presets, tuning, and a record of which notes each division holds. It
+/// does all the allocating, so the audio thread does none.
+pub struct Controller {
+    stops: HashMap<u8, Stop>,
+    presets: HashMap<u8, Vec<Stop>>,
+    program_presets: Vec<ProgramPreset>,
+    preset_defaults: HashMap<u8, Vec<Stop>>,
+    divisions: HashMap<u8, Division>,
+    sample_rate: f32,
+    tuning: Tuning,
+    tuning_choices: Vec<Tuning>,
+    tuning_cc: Option<u8>,
+    channels: HashMap<u8, Channel>,
+    pool: VoicePool,
+    next_id: u64,
+    parser: midi::Parser,
+    /// When the commands for the message being handled take effect.
+    frame: u64,
+    queues: ControlQueues,
+}
+
+/// The control thread's view of a division.
+struct Channel {
+    stops: Vec<Stop>,
+    division: Division,
+    held: Vec<HeldNote>,
+    /// Bank Select as last sent, applied by the next Program Change.
+    bank_msb: u8,
+    bank_lsb: u8,
+}
+
+struct HeldNote {
+    id: u64,
+    key: u8,
+    pipe_key: u8,
+    frequency: f32,
+}
+
+impl Controller {
+    pub fn new(sample_rate: f32, config: &SynthConfig, queues: ControlQueues) -> Self {
+        let (tuning, tuning_choices) = config::get_tunings(config);
+        let named_stops = config::get_named_stops(config);
+        Self {
+            stops: config::get_stops(config, &named_stops),
+            presets: config::get_presets(config, &named_stops),
+            program_presets: config::get_program_presets(config, &named_stops),
+            preset_defaults: config::get_preset_defaults(config, &named_stops),
+            divisions: config::get_divisions(config),
+            sample_rate,
+            tuning,
+            tuning_choices,
+            tuning_cc: config.tuning.cc,
+            channels: HashMap::new(),
+            pool: VoicePool::new(),
+            next_id: 0,
+            parser: midi::Parser::new(),
+            frame: 0,
+            queues,
+        }
+    }
+
+    /// Runs the controller on its own thread and returns the way in.
+    pub fn spawn(mut self) -> MidiSender {
+        let (queue, mut midi_rx): (_, Consumer<(u64, u8)>) = RingBuffer::new(MIDI_CAPACITY);
+        let worker = thread::spawn(move || loop {
+            while let Ok((frame, byte)) = midi_rx.pop() {
+                self.handle_midi(frame, &[byte]);
+            }
+            self.collect();
+            thread::park_timeout(COLLECT_INTERVAL);
+        });
+        MidiSender {
+            queue,
+            worker: worker.thread().clone(),
+        }
+    }
+
+    /// Frees or recycles what the audio thread is done with and prints its
+    /// log. Call regularly.
+    pub fn collect(&mut self) {
+        while let Some(retired) = self.queues.next_retired() {
+            match retired {
+                Retired::Note(note) => self.pool.recycle(note.into_pipes()),
+                Retired::Pipes(pipes) => self.pool.recycle(pipes),
+                Retired::Pipe(pipe) => drop(pipe),
+            }
+        }
+        while let Some(event) = self.queues.next_log() {
+            println!("{}", event);
+        }
+    }
+
+    /// Switches to one of the configured tunings, e.g. "werckmeister3".
+    /// Sounding notes keep their pitch; the next ones use the new tuning.
+    pub fn set_tuning(&mut self, name: &str) -> Result<(), String> {
+        let tuning = self
+            .tuning_choices
+            .iter()
+            .find(|tuning| tuning.name == name)
+            .ok_or_else(|| format!("Unknown tuning: {:?}", name))?;
+        self.use_tuning(tuning.clone());
+        Ok(())
+    }
+
+    fn use_tuning(&mut self, tuning: Tuning) {
+        println!("Using tuning: {}", tuning.name);
+        self.tuning = tuning;
+    }
+
+    /// Handles raw MIDI bytes so that the messages they complete take
+    /// effect when the synth reaches `frame`; 0 means straight away.
+    pub fn handle_midi(&mut self, frame: u64, bytes: &[u8]) {
+        self.frame = frame;
+        for &byte in bytes {
+            if let Some(message) = self.parser.push(byte) {
+                self.handle_message(message);
+            }
+        }
+    }
+
+    fn handle_message(&mut self, message: midi::Message) {
+        if let Some(channel) = message.channel() {
+            self.ensure_channel(channel);
+        }
+        match message {
+            midi::Message::NoteOn {
+                channel,
+                key,
+                velocity,
+            } => self.handle_note_on(channel, key, velocity),
+            midi::Message::NoteOff { channel, key, .. } => self.handle_note_off(channel, key),
+            midi::Message::ControlChange {
+                channel,
+                controller,
+                value,
+            } => self.handle_control_change(channel, controller, value),
+            midi::Message::ProgramChange { channel, program } => {
+                self.handle_program_change(channel, program)
+            }
+            midi::Message::Reset => self.panic(),
+            // Clocks and keep-alives arrive constantly and mean nothing here.
+            message if message.is_real_time() => {}
+            // Nor do expression and sync streams, which can be as busy.
+            midi::Message::PolyPressure { .. }
+            | midi::Message::ChannelPressure { .. }
+            | midi::Message::PitchBend { .. }
+            | midi::Message::TimeCodeQuarterFrame { .. }
+            | midi::Message::SongPosition(_) => {}
+            _ => {
+                println!("Unhandled MIDI message: {:?}", message);
+            }
+        }
+    }
+
+    fn ensure_channel(&mut self, channel: u8) {
+        if self.channels.contains_key(&channel) {
+            return;
+        }
+        let stops = self
+            .preset_defaults
+            .get(&(channel + 1))
+            .cloned()
+            .unwrap_or_else(|| vec![Stop::default()]);
+        let division = self
+            .divisions
+            .get(&(channel + 1))
+            .cloned()
+            .unwrap_or_default();
+        self.queues.send(
+            self.frame,
+            Command::AddDivision {
+                channel,
+                division: Box::new(InternalSynth::new(
+                    self.sample_rate,
+                    channel,
+                    division.clone(),
+                )),
+            },
+        );
+        self.channels.insert(
+            channel,
+            Channel {
+                stops,
+                division,
+                held: Vec::new(),
+                bank_msb: 0,
+                bank_lsb: 0,
+            },
+        );
+    }
+
+    fn handle_note_on(&mut self, channel: u8, key: u8, velocity: u8) {
+        if velocity == 0 {
+            self.handle_note_off(channel, key);
+            return;
+        }
+        let state = &self.channels[&channel];
+        // Keys outside the compass or left unmapped by a Scala keyboard
+        // mapping stay silent.
+        let pipe_key = match &state.division.compass {
+            Some(compass) => compass.map(key),
+            None => Some(key),
+        };
+        let Some(pipe_key) = pipe_key else {
+            return;
+        };
+        let Some(frequency) = self.tuning.frequency(pipe_key) else {
+            return;
+        };
+        let held = HeldNote {
+            id: self.next_id,
+            key,
+            pipe_key,
+            frequency,
+        };
+        self.next_id += 1;
+        let pipes = self.pool.pipes(&state.stops, &held, self.sample_rate);
+        self.queues.send(
+            self.frame,
+            Command::NoteOn {
+                channel,
+                note: Note::new(held.id, held.key, pipes),
+            },
+        );
+        self.channels.get_mut(&channel).unwrap().held.push(held);
+    }
+
+    fn handle_note_off(&mut self, channel: u8, key: u8) {
+        let held = &mut self.channels.get_mut(&channel).unwrap().held;
+        if let Some(index) = held.iter().position(|held| held.key == key) {
+            let held = held.remove(index);
+            self.queues.send(
+                self.frame,
+                Command::NoteOff {
+                    channel,
+                    id: held.id,
+                },
+            );
+        }
+    }
+
+    fn handle_control_change(&mut self, channel: u8, controller: u8, value: u8) {
+        if controller >= ALL_SOUND_OFF {
+            self.handle_channel_mode(channel, controller);
+            return;
+        }
+        if self.channels[&channel]
+            .division
+            .handles_controller(controller)
+        {
+            self.queues.send(
+                self.frame,
+                Command::ControlChange {
+                    channel,
+                    controller,
+                    value,
+                },
+            );
+            return;
+        }
+        match controller {
+            BANK_SELECT_MSB => {
+                self.channels.get_mut(&channel).unwrap().bank_msb = value;
+                return;
+            }
+            BANK_SELECT_LSB => {
+                self.channels.get_mut(&channel).unwrap().bank_lsb = value;
+                return;
+            }
+            _ => {}
+        }
+        if self.tuning_cc == Some(controller) {
+            // The controller range is split evenly between the choices.
+            let index = value as usize * self.tuning_choices.len() / 128;
+            self.use_tuning(self.tuning_choices[index].clone());
+            return;
+        }
+        if let Some(preset) = self.presets.get(&controller).cloned() {
+            println!("Using preset: {:?}", preset);
+            self.use_preset(channel, preset);
+        } else if let Some(stop) = self.stops.get(&controller).cloned() {
+            if value == 0 {
+                self.remove_stop(channel, stop);
+            } else {
+                self.add_stop(channel, stop);
+            }
+        } else {
+            println!(
+                "Unhandled MIDI control change: channel {} controller {} value {}",
+                channel + 1,
+                controller,
+                value
+            );
+        }
+    }
+
+    /// Channel mode messages, CC 120 to 127. Omni and mono/poly switching
+    /// mean nothing to an organ, but imply all notes off as CC 123 does.
+    fn handle_channel_mode(&mut self, channel: u8, controller: u8) {
+        let command = match controller {
+            ALL_SOUND_OFF => Command::AllSoundOff { channel },
+            RESET_ALL_CONTROLLERS => Command::ResetControllers { channel },
+            LOCAL_CONTROL => return,
+            _ => Command::AllNotesOff { channel },
+        };
+        if controller != RESET_ALL_CONTROLLERS {
+            self.channels.get_mut(&channel).unwrap().held.clear();
+        }
+        self.queues.send(self.frame, command);
+    }
+
+    /// Silences every division and resets its controllers, on System Reset.
+    fn panic(&mut self) {
+        println!("Panic: silencing all divisions");
+        for state in self.channels.values_mut() {
+            state.held.clear();
+            state.bank_msb = 0;
+            state.bank_lsb = 0;
+        }
+        self.queues.send(self.frame, Command::Panic);
+    }
+
+    fn handle_program_change(&mut self, channel: u8, program: u8) {
+        let state = &self.channels[&channel];
+        let bank = (state.bank_msb as u16) << 7 | state.bank_lsb as u16;
+        let preset = self.program_presets.iter().find(|preset| {
+            preset.program == program
+                && preset.bank == bank
+                && (preset.channels.is_empty() || preset.channels.contains(&(channel + 1)))
+        });
+        match preset {
+            Some(preset) => {
+                println!("Using preset: {}", preset.name);
+                let stops = preset.stops.clone();
+                self.use_preset(channel, stops);
+            }
+            None => println!(
+                "No preset for program {} in bank {} on channel {}",
+                program,
+                bank,
+                channel + 1
+            ),
+        }
+    }
+
+    fn use_preset(&mut self, channel: u8, stops: Vec<Stop>) {
+        let state = self.channels.get_mut(&channel).unwrap();
+        for note in &state.held {
+            self.queues.send(
+                self.frame,
+                Command::SetPipes {
+                    channel,
+                    id: note.id,
+                    pipes: self.pool.pipes(&stops, note, self.sample_rate),
+                },
+            );
+        }
+        state.stops = stops;
+    }
+
+    fn add_stop(&mut self, channel: u8, stop: Stop) {
+        let state = self.channels.get_mut(&channel).unwrap();
+        for note in &state.held {
+            self.queues.send(
+                self.frame,
+                Command::AddPipes {
+                    channel,
+                    id: note.id,
+                    pipes: self
+                        .pool
+                        .pipes(std::slice::from_ref(&stop), note, self.sample_rate),
+                },
+            );
+        }
+        state.stops.push(stop);
+    }
+
+    fn remove_stop(&mut self, channel: u8, stop: Stop) {
+        let state = self.channels.get_mut(&channel).unwrap();
+        if let Some(position) = state.stops.iter().position(|s| s == &stop) {
+            state.stops.remove(position);
+        }
+        for note in &state.held {
+            self.queues.send(
+                self.frame,
+                Command::RemoveStop {
+                    channel,
+                    id: note.id,
+                    stop: stop.id,
+                    ranks: stop.pipe_count(note.pipe_key),
+                },
+            );
+        }
+    }
+}
+
+/// Pipe vectors with room for `PIPES_PER_VOICE`, reused from notes that
+/// have finished.
+struct VoicePool {
+    spare: Vec<Vec<Oscillator>>,
+}
+
+impl VoicePool {
+    fn new() -> Self {
+        Self {
+            spare: (0..POOL_SIZE)
+                .map(|_| Vec::with_capacity(PIPES_PER_VOICE))
+                .collect(),
+        }
+    }
+
+    /// The pipes `stops` sound for `note`.
+    fn pipes(&mut self, stops: &[Stop], note: &HeldNote, sample_rate: f32) -> Vec<Oscillator> {
+        let mut pipes = self
+            .spare
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(PIPES_PER_VOICE));
+        pipes.extend(stops.iter().flat_map(|stop| {
+            Oscillator::from_stop(stop, note.pipe_key, note.frequency, sample_rate)
+        }));
+        pipes
+    }
+
+    fn recycle(&mut self, mut pipes: Vec<Oscillator>) {
+        if self.spare.len() < POOL_SIZE && pipes.capacity() >= PIPES_PER_VOICE {
+            pipes.clear();
+            self.spare.push(pipes);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use super::compass::Compass;
+use super::effect::Effect;
+use super::swell::SwellSettings;
+use super::tremulant::TremulantSettings;
+use super::wind::WindSettings;
+use crate::config::DivisionConfig;
+
+/// Output level of a division without a `gain` of its own, -20 dB. Several
+/// stops sounding a full chord sum to well above full scale, so this leaves
+/// headroom before the master chain.
+pub const DEFAULT_GAIN: f32 = 0.1;
+
+/// Per-division settings shared by every note played on a MIDI channel.
+#[derive(Debug, Clone)]
+pub struct Division {
+    pub tremulant: Option<TremulantSettings>,
+    pub wind: Option<WindSettings>,
+    /// Present for enclosed divisions.
+    pub swell: Option<SwellSettings>,
+    /// The division's own effect chain, replacing the default one.
+    pub effects: Option<Vec<Effect>>,
+    /// Keys outside it are silent or fold back into it.
+    pub compass: Option<Compass>,
+    /// Linear gain applied to the division's output.
+    pub gain: f32,
+}
+
+impl Default for Division {
+    fn default() -> Self {
+        Self {
+            tremulant: None,
+            wind: None,
+            swell: None,
+            effects: None,
+            compass: None,
+            gain: DEFAULT_GAIN,
+        }
+    }
+}
+
+impl Division {
+    pub fn new(config: &DivisionConfig) -> Self {
+        Self {
+            tremulant: config.tremulant.as_ref().map(TremulantSettings::new),
+            wind: config.wind.as_ref().map(WindSettings::new),
+            swell: config.swell.as_ref().map(SwellSettings::new),
+            effects: config.effects.as_deref().map(Effect::chain),
+            compass: config.compass.as_ref().map(Compass::new),
+            gain: config
+                .gain
+                .map_or(DEFAULT_GAIN, |gain_db| 10.0f32.powf(gain_db / 20.0)),
+        }
+    }
+
+    /// Whether `controller` drives the tremulant, wind or swell box.
+    pub fn handles_controller(&self, controller: u8) -> bool {
+        let controller = Some(controller);
+        self.tremulant.as_ref().is_some_and(|tremulant| {
+            [tremulant.toggle_cc, tremulant.speed_cc, tremulant.depth_cc].contains(&controller)
+        }) || self
+            .wind
+            .as_ref()
+            .is_some_and(|wind| wind.pressure_cc == controller)
+            || self
+                .swell
+                .as_ref()
+                .is_some_and(|swell| Some(swell.cc) == controller)
+    }
+}
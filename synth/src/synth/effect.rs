@@ -0,0 +1,169 @@
+use super::filters::{
+    Biquad, BiquadKind, ConvolutionReverb, DualMono, HallReverb, HallReverbSettings,
+    ImpulseResponse, LowPass, SimpleReverb, StateVariable, StateVariableMode, StereoFilter,
+};
+use crate::config::EffectConfig;
+use std::sync::Arc;
+
+const MAX_ECHO_FEEDBACK: f32 = 0.99;
+
+/// One entry of an effect chain. Parameters are stored independently of the
+/// sample rate; `build` creates the filter for a particular rate.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// One-pole low-pass, cutoff in Hz.
+    OnePole {
+        frequency: f32,
+    },
+    Biquad {
+        kind: BiquadKind,
+        frequency: f32,
+        q: f32,
+        gain_db: f32,
+    },
+    StateVariable {
+        mode: StateVariableMode,
+        frequency: f32,
+        q: f32,
+    },
+    /// Single feedback delay line; delay in milliseconds.
+    Echo {
+        delay: f32,
+        feedback: f32,
+        mix: f32,
+    },
+    Reverb(HallReverbSettings),
+    Convolution {
+        impulse: Arc<ImpulseResponse>,
+        mix: f32,
+    },
+}
+
+impl Effect {
+    pub fn new(config: &EffectConfig) -> Result<Self, String> {
+        let biquad = |kind| Effect::Biquad {
+            kind,
+            frequency: config.frequency,
+            q: config.q,
+            gain_db: config.gain,
+        };
+        match config.kind.as_str() {
+            "one_pole" => Ok(Effect::OnePole {
+                frequency: config.frequency,
+            }),
+            "low_pass" => Ok(biquad(BiquadKind::LowPass)),
+            "high_pass" => Ok(biquad(BiquadKind::HighPass)),
+            "band_pass" => Ok(biquad(BiquadKind::BandPass)),
+            "notch" => Ok(biquad(BiquadKind::Notch)),
+            "low_shelf" => Ok(biquad(BiquadKind::LowShelf)),
+            "high_shelf" => Ok(biquad(BiquadKind::HighShelf)),
+            "peaking" => Ok(biquad(BiquadKind::Peaking)),
+            "state_variable" => Ok(Effect::StateVariable {
+                mode: match config.mode.as_str() {
+                    "high_pass" => StateVariableMode::HighPass,
+                    "band_pass" => StateVariableMode::BandPass,
+                    "notch" => StateVariableMode::Notch,
+                    _ => StateVariableMode::LowPass,
+                },
+                frequency: config.frequency,
+                q: config.q,
+            }),
+            "echo" if !(config.delay >= 0.0 && config.delay.is_finite()) => {
+                Err(format!("Invalid echo delay: {}", config.delay))
+            }
+            // Delays under a sample are rounded up to one when built; feedback
+            // of 1 or more would never die away.
+            "echo" => Ok(Effect::Echo {
+                delay: config.delay,
+                feedback: config.feedback.clamp(0.0, MAX_ECHO_FEEDBACK),
+                mix: config.mix,
+            }),
+            "reverb" => Ok(Effect::Reverb(HallReverbSettings {
+                rt60: config.rt60,
+                pre_delay: config.pre_delay,
+                damping: config.damping,
+                size: config.size,
+                width: config.width,
+                mix: config.mix,
+            })),
+            "convolution" => match ImpulseResponse::load(&config.path) {
+                Ok(impulse) => Ok(Effect::Convolution {
+                    impulse: Arc::new(impulse),
+                    mix: config.mix,
+                }),
+                Err(e) => Err(format!(
+                    "Error loading impulse response {:?}: {}",
+                    config.path, e
+                )),
+            },
+            _ => Err(format!("Unknown effect type: {:?}", config.kind)),
+        }
+    }
+
+    /// Parses a configured chain, dropping bypassed and invalid entries.
+    pub fn chain(configs: &[EffectConfig]) -> Vec<Self> {
+        configs
+            .iter()
+            .filter(|config| !config.bypass)
+            .filter_map(|config| match Effect::new(config) {
+                Ok(effect) => Some(effect),
+                Err(e) => {
+                    println!("Skipping effect: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The chain used by divisions that don't configure their own.
+    pub fn default_chain() -> Vec<Self> {
+        vec![
+            Effect::OnePole { frequency: 800.0 },
+            Effect::Reverb(HallReverbSettings {
+                rt60: 2.5,
+                pre_delay: 20.0,
+                damping: 0.4,
+                size: 1.0,
+                width: 1.0,
+                mix: 0.3,
+            }),
+        ]
+    }
+
+    pub fn build(&self, sample_rate: f32) -> Box<dyn StereoFilter> {
+        match *self {
+            Effect::OnePole { frequency } => {
+                Box::new(DualMono::new(|| LowPass::new(sample_rate, frequency)))
+            }
+            Effect::Biquad {
+                kind,
+                frequency,
+                q,
+                gain_db,
+            } => Box::new(DualMono::new(|| {
+                Biquad::new(kind, sample_rate, frequency, q, gain_db)
+            })),
+            Effect::StateVariable { mode, frequency, q } => Box::new(DualMono::new(|| {
+                StateVariable::new(mode, sample_rate, frequency, q)
+            })),
+            Effect::Echo {
+                delay,
+                feedback,
+                mix,
+            } => Box::new(DualMono::new(|| {
+                SimpleReverb::new(sample_rate, delay, feedback, mix)
+            })),
+            Effect::Reverb(settings) => Box::new(HallReverb::new(sample_rate, settings)),
+            Effect::Convolution { ref impulse, mix } => {
+                Box::new(ConvolutionReverb::new(sample_rate, impulse, mix))
+            }
+        }
+    }
+}
+
+pub fn build_chain(effects: &[Effect], sample_rate: f32) -> Vec<Box<dyn StereoFilter>> {
+    effects
+        .iter()
+        .map(|effect| effect.build(sample_rate))
+        .collect()
+}
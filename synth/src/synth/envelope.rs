@@ -0,0 +1,196 @@
+use crate::config::EnvelopeConfig;
+
+/// Pitch at which a stop's envelope times apply unscaled (middle C).
+const REFERENCE_FREQUENCY: f32 = 261.63;
+/// Exponential stages are considered done once within -60 dB of their target.
+const EXPONENTIAL_TARGET_RATIO: f32 = 1e-3;
+const EPSILON: f32 = 1e-6;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EnvelopeCurve {
+    Linear,
+    Exponential,
+}
+
+impl EnvelopeCurve {
+    pub fn parse(curve: &str) -> Self {
+        match curve {
+            "exponential" => EnvelopeCurve::Exponential,
+            _ => EnvelopeCurve::Linear,
+        }
+    }
+}
+
+/// Per-stop ADSR settings. Times are in seconds for a pipe at middle C.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EnvelopeSettings {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+    pub curve: EnvelopeCurve,
+    /// How strongly times follow pitch: 0 keeps them fixed, 1 makes them
+    /// inversely proportional to frequency so an octave down speaks twice as
+    /// slowly.
+    pub pitch_scaling: f32,
+}
+
+impl Default for EnvelopeSettings {
+    fn default() -> Self {
+        Self {
+            attack: 0.05,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.1,
+            curve: EnvelopeCurve::Linear,
+            pitch_scaling: 0.0,
+        }
+    }
+}
+
+impl EnvelopeSettings {
+    pub fn new(config: &EnvelopeConfig) -> Self {
+        Self {
+            attack: config.attack,
+            decay: config.decay,
+            sustain: config.sustain.clamp(0.0, 1.0),
+            release: config.release,
+            curve: EnvelopeCurve::parse(&config.curve),
+            pitch_scaling: config.pitch_scaling,
+        }
+    }
+
+    /// Returns the settings with times scaled for a pipe sounding at `frequency`.
+    pub fn at_frequency(&self, frequency: f32) -> Self {
+        let scale = (REFERENCE_FREQUENCY / frequency.max(1.0))
+            .powf(self.pitch_scaling)
+            .clamp(0.1, 10.0);
+        Self {
+            attack: self.attack * scale,
+            decay: self.decay * scale,
+            release: self.release * scale,
+            ..*self
+        }
+    }
+}
+
+pub struct Envelope {
+    pub value: f32,
+    attack: Stage,
+    decay: Stage,
+    release: Stage,
+    sustain: f32,
+    curve: EnvelopeCurve,
+    pub state: EnvelopeState,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Idle,
+}
+
+/// Per-sample step sizes for one stage, precomputed so the audio loop
+/// doesn't call `powf`.
+struct Stage {
+    samples: f32,
+    linear_step: f32,
+    exponential_coefficient: f32,
+}
+
+impl Stage {
+    fn new(time: f32, sample_rate: f32) -> Self {
+        let samples = time * sample_rate;
+        Self {
+            samples,
+            linear_step: 1.0 / samples,
+            exponential_coefficient: EXPONENTIAL_TARGET_RATIO.powf(1.0 / samples),
+        }
+    }
+
+    /// Moves `value` one sample towards `target`, returning true once the
+    /// target has been reached.
+    fn approach(&self, value: &mut f32, target: f32, curve: EnvelopeCurve) -> bool {
+        if self.samples < 1.0 {
+            *value = target;
+            return true;
+        }
+        match curve {
+            EnvelopeCurve::Linear => {
+                // Steps are relative to full scale, so a release from a
+                // lower level finishes sooner rather than sounding longer.
+                if *value < target {
+                    *value = (*value + self.linear_step).min(target);
+                } else {
+                    *value = (*value - self.linear_step).max(target);
+                }
+            }
+            EnvelopeCurve::Exponential => {
+                *value = target + (*value - target) * self.exponential_coefficient;
+                if (*value - target).abs() < EXPONENTIAL_TARGET_RATIO {
+                    *value = target;
+                }
+            }
+        }
+        *value == target
+    }
+}
+
+impl Envelope {
+    pub fn new(sample_rate: f32, settings: EnvelopeSettings) -> Self {
+        Self {
+            value: EPSILON, // Start at a very low value to avoid clicks
+            attack: Stage::new(settings.attack, sample_rate),
+            decay: Stage::new(settings.decay, sample_rate),
+            release: Stage::new(settings.release, sample_rate),
+            sustain: settings.sustain,
+            curve: settings.curve,
+            state: EnvelopeState::Attack,
+        }
+    }
+
+    fn next(&mut self) -> f32 {
+        match self.state {
+            EnvelopeState::Attack => {
+                if self.attack.approach(&mut self.value, 1.0, self.curve) {
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                if self
+                    .decay
+                    .approach(&mut self.value, self.sustain, self.curve)
+                {
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Release => {
+                if self.release.approach(&mut self.value, 0.0, self.curve) {
+                    self.state = EnvelopeState::Idle;
+                }
+            }
+            EnvelopeState::Sustain | EnvelopeState::Idle => {}
+        }
+        self.value
+    }
+
+    /// Fills `output` with the next values of the envelope.
+    pub fn render(&mut self, output: &mut [f32]) {
+        match self.state {
+            // Steady states need no per-sample bookkeeping.
+            EnvelopeState::Sustain | EnvelopeState::Idle => output.fill(self.value),
+            _ => output.iter_mut().for_each(|value| *value = self.next()),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state == EnvelopeState::Idle
+    }
+
+    pub fn trigger_release(&mut self) {
+        self.state = EnvelopeState::Release;
+    }
+}
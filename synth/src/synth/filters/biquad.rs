@@ -0,0 +1,132 @@
+use super::Filter;
+use std::f32::consts::PI;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BiquadKind {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+    LowShelf,
+    HighShelf,
+    Peaking,
+}
+
+/// Second-order IIR filter using the RBJ audio EQ cookbook formulas.
+///
+/// Parameters are given in Hz, Q and dB, and the coefficients are
+/// recomputed whenever the sample rate changes so a filter sounds the same
+/// at 44.1 kHz and 96 kHz.
+pub struct Biquad {
+    kind: BiquadKind,
+    frequency: f32,
+    q: f32,
+    gain_db: f32,
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// `gain_db` only affects the shelving and peaking kinds.
+    pub fn new(kind: BiquadKind, sample_rate: f32, frequency: f32, q: f32, gain_db: f32) -> Self {
+        let mut biquad = Self {
+            kind,
+            frequency,
+            q: q.max(0.01),
+            gain_db,
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            z1: 0.0,
+            z2: 0.0,
+        };
+        biquad.set_sample_rate(sample_rate);
+        biquad
+    }
+}
+
+impl Filter for Biquad {
+    fn process(&mut self, input: f32) -> f32 {
+        // Transposed direct form II: good numerical behaviour in f32.
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+
+    fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        let frequency = self.frequency.clamp(1.0, sample_rate * 0.49);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * self.q);
+        let a = 10.0f32.powf(self.gain_db / 40.0);
+        let (b0, b1, b2, a0, a1, a2) = match self.kind {
+            BiquadKind::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BiquadKind::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            // Constant 0 dB peak gain.
+            BiquadKind::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadKind::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadKind::LowShelf => {
+                let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+                    (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+                )
+            }
+            BiquadKind::HighShelf => {
+                let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+                    (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+                )
+            }
+            BiquadKind::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+        };
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = a1 / a0;
+        self.a2 = a2 / a0;
+    }
+}
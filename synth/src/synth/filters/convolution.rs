@@ -0,0 +1,232 @@
+use super::{Filter, StereoFilter};
+use crate::synth::wav::Wav;
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Samples per partition. The wet signal lags the dry one by this much.
+const PARTITION_SIZE: usize = 256;
+
+/// An impulse response as loaded from disk, at its original sample rate.
+#[derive(PartialEq)]
+pub struct ImpulseResponse {
+    path: String,
+    sample_rate: f32,
+    /// One or two channels.
+    channels: Vec<Vec<f32>>,
+}
+
+impl ImpulseResponse {
+    pub fn load(path: &str) -> Result<Self, hound::Error> {
+        let mut wav = Wav::load(Path::new(path))?;
+        // Anything beyond a stereo pair is ignored.
+        wav.channels.truncate(2);
+        Ok(Self {
+            path: path.to_string(),
+            sample_rate: wav.sample_rate,
+            channels: wav.channels,
+        })
+    }
+
+    /// Returns the channels resampled to `sample_rate` and scaled to unit
+    /// energy, so the wet level doesn't depend on how the room was recorded.
+    fn prepare(&self, sample_rate: f32) -> Vec<Vec<f32>> {
+        let mut channels: Vec<Vec<f32>> = self
+            .channels
+            .iter()
+            .map(|channel| resample(channel, self.sample_rate, sample_rate))
+            .collect();
+        let energy = channels
+            .iter()
+            .map(|channel| channel.iter().map(|sample| sample * sample).sum::<f32>())
+            .fold(0.0f32, f32::max);
+        if energy > 0.0 {
+            let scale = 1.0 / energy.sqrt();
+            channels
+                .iter_mut()
+                .for_each(|channel| channel.iter_mut().for_each(|sample| *sample *= scale));
+        }
+        channels
+    }
+}
+
+impl fmt::Debug for ImpulseResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImpulseResponse")
+            .field("path", &self.path)
+            .field("sample_rate", &self.sample_rate)
+            .field("channels", &self.channels.len())
+            .field("length", &self.channels[0].len())
+            .finish()
+    }
+}
+
+/// Linear-interpolation resampler; adequate for a reverb tail.
+fn resample(samples: &[f32], from: f32, to: f32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = from / to;
+    let length = (samples.len() as f32 / ratio) as usize;
+    (0..length)
+        .map(|i| {
+            let position = i as f32 * ratio;
+            let index = position as usize;
+            let fraction = position - index as f32;
+            let a = samples[index];
+            let b = samples.get(index + 1).copied().unwrap_or(0.0);
+            a + (b - a) * fraction
+        })
+        .collect()
+}
+
+/// Uniformly partitioned overlap-save FFT convolution of one channel.
+pub struct Convolver {
+    fft: Arc<dyn RealToComplex<f32>>,
+    ifft: Arc<dyn ComplexToReal<f32>>,
+    /// Spectra of the impulse response, one per partition.
+    partitions: Vec<Vec<Complex<f32>>>,
+    /// Spectra of recent input blocks, used as a ring buffer.
+    history: Vec<Vec<Complex<f32>>>,
+    history_index: usize,
+    /// The previous and the current input block.
+    input: Vec<f32>,
+    output: Vec<f32>,
+    position: usize,
+    fft_buffer: Vec<f32>,
+    accumulator: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Convolver {
+    pub fn new(impulse: &[f32]) -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(2 * PARTITION_SIZE);
+        let ifft = planner.plan_fft_inverse(2 * PARTITION_SIZE);
+        let mut scratch =
+            vec![Complex::default(); fft.get_scratch_len().max(ifft.get_scratch_len())];
+        let partitions: Vec<Vec<Complex<f32>>> = impulse
+            .chunks(PARTITION_SIZE)
+            .map(|chunk| {
+                let mut padded = vec![0.0; 2 * PARTITION_SIZE];
+                padded[..chunk.len()].copy_from_slice(chunk);
+                let mut spectrum = fft.make_output_vec();
+                fft.process_with_scratch(&mut padded, &mut spectrum, &mut scratch)
+                    .unwrap();
+                spectrum
+            })
+            .collect();
+        let partition_count = partitions.len().max(1);
+        Self {
+            history: vec![fft.make_output_vec(); partition_count],
+            history_index: 0,
+            partitions,
+            input: vec![0.0; 2 * PARTITION_SIZE],
+            output: vec![0.0; PARTITION_SIZE],
+            position: 0,
+            fft_buffer: vec![0.0; 2 * PARTITION_SIZE],
+            accumulator: fft.make_output_vec(),
+            scratch,
+            fft,
+            ifft,
+        }
+    }
+
+    fn process_block(&mut self) {
+        let history_len = self.history.len();
+        self.fft_buffer.copy_from_slice(&self.input);
+        self.fft
+            .process_with_scratch(
+                &mut self.fft_buffer,
+                &mut self.history[self.history_index],
+                &mut self.scratch,
+            )
+            .unwrap();
+        self.input.copy_within(PARTITION_SIZE.., 0);
+
+        self.accumulator.fill(Complex::default());
+        for (age, partition) in self.partitions.iter().enumerate() {
+            let block = &self.history[(self.history_index + history_len - age) % history_len];
+            for ((sum, x), h) in self.accumulator.iter_mut().zip(block).zip(partition) {
+                *sum += x * h;
+            }
+        }
+        // The inverse transform rejects rounding noise in these bins.
+        self.accumulator[0].im = 0.0;
+        self.accumulator[PARTITION_SIZE].im = 0.0;
+        self.ifft
+            .process_with_scratch(
+                &mut self.accumulator,
+                &mut self.fft_buffer,
+                &mut self.scratch,
+            )
+            .unwrap();
+
+        // Only the second half is free of circular wrap-around.
+        let scale = 1.0 / (2 * PARTITION_SIZE) as f32;
+        for (output, sample) in self
+            .output
+            .iter_mut()
+            .zip(&self.fft_buffer[PARTITION_SIZE..])
+        {
+            *output = sample * scale;
+        }
+        self.history_index = (self.history_index + 1) % history_len;
+    }
+}
+
+impl Filter for Convolver {
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.output[self.position];
+        self.input[PARTITION_SIZE + self.position] = input;
+        self.position += 1;
+        if self.position == PARTITION_SIZE {
+            self.process_block();
+            self.position = 0;
+        }
+        output
+    }
+
+    fn reset(&mut self) {
+        for spectrum in self.history.iter_mut() {
+            spectrum.fill(Complex::default());
+        }
+        self.input.fill(0.0);
+        self.output.fill(0.0);
+    }
+}
+
+/// Convolves each channel with an impulse response. A mono response is used
+/// for both channels; a stereo one maps left to left and right to right.
+pub struct ConvolutionReverb {
+    left: Convolver,
+    right: Convolver,
+    mix: f32,
+}
+
+impl ConvolutionReverb {
+    pub fn new(sample_rate: f32, impulse: &ImpulseResponse, mix: f32) -> Self {
+        let channels = impulse.prepare(sample_rate);
+        Self {
+            left: Convolver::new(&channels[0]),
+            right: Convolver::new(channels.last().unwrap()),
+            mix: mix.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl StereoFilter for ConvolutionReverb {
+    fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        (
+            left * (1.0 - self.mix) + self.left.process(left) * self.mix,
+            right * (1.0 - self.mix) + self.right.process(right) * self.mix,
+        )
+    }
+
+    fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+    }
+}
@@ -1,3 +1,17 @@
 pub trait Filter: Send {
     fn process(&mut self, input: f32) -> f32;
+
+    /// Filters `buffer` in place. Implementations with per-sample state can
+    /// rely on the default, which the compiler inlines per filter type.
+    fn process_block(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.process(*sample);
+        }
+    }
+
+    /// Recomputes any coefficients that depend on the sample rate.
+    fn set_sample_rate(&mut self, _sample_rate: f32) {}
+
+    /// Forgets all past input, silencing any tail still ringing out.
+    fn reset(&mut self);
 }
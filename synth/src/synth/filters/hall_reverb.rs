@@ -0,0 +1,169 @@
+use super::StereoFilter;
+
+/// Feedback delay lengths in milliseconds at size 1.0, chosen to be
+/// mutually prime-ish so the modes don't pile up into a metallic ring.
+const LINE_TIMES: [f32; 8] = [29.7, 37.1, 41.1, 43.7, 53.9, 59.3, 67.1, 73.3];
+/// Series allpass diffusers that smear the input into a dense onset.
+const DIFFUSER_TIMES: [f32; 4] = [4.7, 3.6, 12.7, 9.3];
+const DIFFUSER_GAIN: f32 = 0.7;
+/// Signs used to derive two decorrelated outputs from the delay lines.
+const LEFT_TAPS: [f32; 8] = [1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0];
+const RIGHT_TAPS: [f32; 8] = [1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, -1.0];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HallReverbSettings {
+    /// Time in seconds for the tail to decay by 60 dB.
+    pub rt60: f32,
+    /// Gap in milliseconds before the reverberation starts.
+    pub pre_delay: f32,
+    /// High-frequency absorption per pass, from 0 (bright) to 1 (dull).
+    pub damping: f32,
+    /// Scales the room's delay lengths; larger rooms sound sparser.
+    pub size: f32,
+    /// Stereo spread of the tail, from 0 (mono) to 1.
+    pub width: f32,
+    /// Wet/dry mix (0.0 = dry, 1.0 = wet).
+    pub mix: f32,
+}
+
+/// Eight-line feedback delay network with a Hadamard feedback matrix.
+pub struct HallReverb {
+    pre_delay: DelayLine,
+    diffusers: Vec<DelayLine>,
+    lines: Vec<DelayLine>,
+    gains: [f32; 8],
+    damping: f32,
+    damping_state: [f32; 8],
+    width: f32,
+    mix: f32,
+}
+
+impl HallReverb {
+    pub fn new(sample_rate: f32, settings: HallReverbSettings) -> Self {
+        let samples = |ms: f32| ((ms * sample_rate / 1000.0).round() as usize).max(1);
+        let size = settings.size.clamp(0.1, 4.0);
+        let rt60 = settings.rt60.max(0.01);
+        let lines: Vec<DelayLine> = LINE_TIMES
+            .iter()
+            .map(|time| DelayLine::new(samples(time * size)))
+            .collect();
+        let mut gains = [0.0; 8];
+        for (gain, line) in gains.iter_mut().zip(lines.iter()) {
+            *gain = 10.0f32.powf(-3.0 * line.len() as f32 / (rt60 * sample_rate));
+        }
+        Self {
+            pre_delay: DelayLine::new(samples(settings.pre_delay.max(0.0))),
+            diffusers: DIFFUSER_TIMES
+                .iter()
+                .map(|time| DelayLine::new(samples(time * size)))
+                .collect(),
+            lines,
+            gains,
+            damping: settings.damping.clamp(0.0, 0.99),
+            damping_state: [0.0; 8],
+            width: settings.width.clamp(0.0, 1.0),
+            mix: settings.mix.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl StereoFilter for HallReverb {
+    fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let mut input = self.pre_delay.process(0.5 * (left + right));
+        for diffuser in self.diffusers.iter_mut() {
+            let delayed = diffuser.read();
+            let w = input - DIFFUSER_GAIN * delayed;
+            diffuser.write(w);
+            input = delayed + DIFFUSER_GAIN * w;
+        }
+
+        let mut outputs = [0.0; 8];
+        for (i, line) in self.lines.iter().enumerate() {
+            outputs[i] = line.read();
+        }
+        let mut feedback = outputs;
+        for (i, value) in feedback.iter_mut().enumerate() {
+            self.damping_state[i] += (1.0 - self.damping) * (*value - self.damping_state[i]);
+            *value = self.damping_state[i] * self.gains[i];
+        }
+        hadamard(&mut feedback);
+        for (line, value) in self.lines.iter_mut().zip(feedback) {
+            line.write(value + input);
+        }
+
+        let wet_left: f32 = outputs.iter().zip(LEFT_TAPS).map(|(y, s)| y * s).sum();
+        let wet_right: f32 = outputs.iter().zip(RIGHT_TAPS).map(|(y, s)| y * s).sum();
+        let mid = 0.5 * (wet_left + wet_right);
+        let side = 0.5 * (wet_left - wet_right) * self.width;
+        // The taps sum eight lines; scale back to roughly the input level.
+        let scale = 0.25;
+        (
+            left * (1.0 - self.mix) + (mid + side) * scale * self.mix,
+            right * (1.0 - self.mix) + (mid - side) * scale * self.mix,
+        )
+    }
+
+    fn reset(&mut self) {
+        self.pre_delay.reset();
+        for line in self.diffusers.iter_mut().chain(self.lines.iter_mut()) {
+            line.reset();
+        }
+        self.damping_state = [0.0; 8];
+    }
+}
+
+/// In-place, energy-preserving 8-point Walsh-Hadamard transform.
+fn hadamard(values: &mut [f32; 8]) {
+    let mut span = 1;
+    while span < values.len() {
+        for start in (0..values.len()).step_by(span * 2) {
+            for i in start..start + span {
+                let (a, b) = (values[i], values[i + span]);
+                values[i] = a + b;
+                values[i + span] = a - b;
+            }
+        }
+        span *= 2;
+    }
+    let scale = 1.0 / (values.len() as f32).sqrt();
+    values.iter_mut().for_each(|value| *value *= scale);
+}
+
+/// Fixed-length circular delay.
+struct DelayLine {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl DelayLine {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length],
+            index: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The sample written `len()` samples ago.
+    fn read(&self) -> f32 {
+        self.buffer[self.index]
+    }
+
+    fn write(&mut self, value: f32) {
+        self.buffer[self.index] = value;
+        self.index = (self.index + 1) % self.buffer.len();
+    }
+
+    fn process(&mut self, value: f32) -> f32 {
+        let output = self.read();
+        self.write(value);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+    }
+}
@@ -1,22 +1,36 @@
 use super::Filter;
+use std::f32::consts::TAU;
 
+/// One-pole low-pass with a 6 dB/octave slope.
 pub struct LowPass {
-    cutoff: f32,
+    frequency: f32,
+    coefficient: f32,
     last_output: f32,
 }
 
 impl LowPass {
-    pub fn new(cutoff: f32) -> Self {
-        Self {
-            cutoff,
+    pub fn new(sample_rate: f32, frequency: f32) -> Self {
+        let mut filter = Self {
+            frequency,
+            coefficient: 1.0,
             last_output: 0.0,
-        }
+        };
+        filter.set_sample_rate(sample_rate);
+        filter
     }
 }
 
 impl Filter for LowPass {
     fn process(&mut self, input: f32) -> f32 {
-        self.last_output += self.cutoff * (input - self.last_output);
+        self.last_output += self.coefficient * (input - self.last_output);
         self.last_output
     }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.coefficient = 1.0 - (-TAU * self.frequency / sample_rate).exp();
+    }
+
+    fn reset(&mut self) {
+        self.last_output = 0.0;
+    }
 }
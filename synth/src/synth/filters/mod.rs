@@ -1,6 +1,16 @@
+mod biquad;
+mod convolution;
 mod filter;
+mod hall_reverb;
 mod low_pass;
 mod reverb;
+mod state_variable;
+mod stereo;
+pub use biquad::{Biquad, BiquadKind};
+pub use convolution::{ConvolutionReverb, ImpulseResponse};
 pub use filter::Filter;
+pub use hall_reverb::{HallReverb, HallReverbSettings};
 pub use low_pass::LowPass;
 pub use reverb::SimpleReverb;
+pub use state_variable::{StateVariable, StateVariableMode};
+pub use stereo::{DualMono, StereoFilter};
@@ -10,8 +10,9 @@ pub struct SimpleReverb {
 impl SimpleReverb {
     /// Creates a new `SimpleReverb` with the given parameters.
     pub fn new(sample_rate: f32, delay_ms: f32, feedback: f32, mix: f32) -> Self {
-        // Convert delay from milliseconds to samples
-        let delay_samples = ((sample_rate * delay_ms) / 1000.0).round() as usize;
+        // Convert delay from milliseconds to samples; the buffer needs at
+        // least one
+        let delay_samples = (((sample_rate * delay_ms) / 1000.0).round() as usize).max(1);
 
         SimpleReverb {
             delay_line: vec![0.0; delay_samples], // Initialize delay buffer
@@ -41,4 +42,8 @@ impl Filter for SimpleReverb {
 
         output
     }
+
+    fn reset(&mut self) {
+        self.delay_line.fill(0.0);
+    }
 }
@@ -0,0 +1,75 @@
+use super::Filter;
+use std::f32::consts::PI;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StateVariableMode {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+/// Topology-preserving (trapezoidal) state variable filter. Unlike the
+/// biquad it stays well behaved when its cutoff is modulated per sample.
+pub struct StateVariable {
+    mode: StateVariableMode,
+    frequency: f32,
+    q: f32,
+    sample_rate: f32,
+    g: f32,
+    k: f32,
+    a1: f32,
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl StateVariable {
+    pub fn new(mode: StateVariableMode, sample_rate: f32, frequency: f32, q: f32) -> Self {
+        let mut filter = Self {
+            mode,
+            frequency,
+            q: q.max(0.01),
+            sample_rate,
+            g: 0.0,
+            k: 0.0,
+            a1: 0.0,
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+        };
+        filter.update_coefficients();
+        filter
+    }
+
+    fn update_coefficients(&mut self) {
+        let frequency = self.frequency.clamp(1.0, self.sample_rate * 0.49);
+        self.g = (PI * frequency / self.sample_rate).tan();
+        self.k = 1.0 / self.q;
+        self.a1 = 1.0 / (1.0 + self.g * (self.g + self.k));
+    }
+}
+
+impl Filter for StateVariable {
+    fn process(&mut self, input: f32) -> f32 {
+        let v1 = self.a1 * (self.ic1eq + self.g * (input - self.ic2eq));
+        let v2 = self.ic2eq + self.g * v1;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+        match self.mode {
+            StateVariableMode::LowPass => v2,
+            // Scaled by k for unity gain at the centre frequency.
+            StateVariableMode::BandPass => self.k * v1,
+            StateVariableMode::HighPass => input - self.k * v1 - v2,
+            StateVariableMode::Notch => input - self.k * v1,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.update_coefficients();
+    }
+
+    fn reset(&mut self) {
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
+}
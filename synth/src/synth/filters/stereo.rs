@@ -0,0 +1,47 @@
+use super::Filter;
+
+pub trait StereoFilter: Send {
+    fn process(&mut self, left: f32, right: f32) -> (f32, f32);
+
+    /// Filters a block in place, so the chain pays for dynamic dispatch once
+    /// per block rather than once per sample.
+    fn process_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            (*left, *right) = self.process(*left, *right);
+        }
+    }
+
+    /// Forgets all past input, silencing any tail still ringing out.
+    fn reset(&mut self);
+}
+
+/// Runs an independent copy of a mono filter on each channel.
+pub struct DualMono<F: Filter> {
+    left: F,
+    right: F,
+}
+
+impl<F: Filter> DualMono<F> {
+    pub fn new(make: impl Fn() -> F) -> Self {
+        Self {
+            left: make(),
+            right: make(),
+        }
+    }
+}
+
+impl<F: Filter> StereoFilter for DualMono<F> {
+    fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        (self.left.process(left), self.right.process(right))
+    }
+
+    fn process_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.left.process_block(left);
+        self.right.process_block(right);
+    }
+
+    fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+    }
+}
@@ -1,10 +1,28 @@
+mod additive;
+mod compass;
 mod config;
+mod control;
+mod division;
+mod effect;
+mod envelope;
 mod filters;
 mod note;
 mod oscillator;
+mod pan;
+mod queue;
+mod sample;
 mod stop;
+mod swell;
+#[allow(clippy::module_inception)]
 mod synth; // TODO
 mod thingy;
+mod transient;
+mod tremulant;
+mod tuning;
+mod wav;
 mod waveform;
+mod wind;
+pub use control::{Controller, MidiSender};
+pub use division::Division;
 pub use stop::Stop;
 pub use synth::Synth;
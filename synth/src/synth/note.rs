@@ -1,50 +1,64 @@
 use super::oscillator::Oscillator;
-use super::stop::Stop;
+use super::queue::{AudioQueues, LogEvent, Retired};
+use super::stop::StopId;
 
+/// A sounding key. Its pipes are built on the control thread; the vector
+/// comes with room to spare so adding stops later does not allocate.
 pub struct Note {
-    sample_rate: f32,
+    /// Unique per note, so the control thread can address it.
+    pub id: u64,
+    pub key: u8,
     oscillators: Vec<Oscillator>,
-    pub frequency: f32,
     pub is_released: bool,
 }
 
 impl Note {
-    pub fn new(frequency: f32, sample_rate: f32, stops: &[Stop]) -> Self {
-        let oscillators = stops
-            .iter()
-            .map(|stop| Oscillator::from_stop(stop, frequency, sample_rate))
-            .collect();
+    pub fn new(id: u64, key: u8, oscillators: Vec<Oscillator>) -> Self {
         Self {
-            sample_rate,
+            id,
+            key,
             oscillators,
-            frequency,
             is_released: false,
         }
     }
 
-    pub fn use_preset(&mut self, stops: &[Stop]) {
-        if self.is_released {
-            return;
-        }
-        self.oscillators = stops
-            .iter()
-            .map(|stop| Oscillator::from_stop(stop, self.frequency, self.sample_rate))
-            .collect();
+    /// Hands back the (empty) pipe vector for reuse.
+    pub fn into_pipes(self) -> Vec<Oscillator> {
+        self.oscillators
+    }
+
+    /// Swaps in `pipes` and returns the previous ones.
+    pub fn set_pipes(&mut self, pipes: Vec<Oscillator>) -> Vec<Oscillator> {
+        std::mem::replace(&mut self.oscillators, pipes)
     }
 
-    pub fn add_stop(&mut self, stop: &Stop) {
-        self.oscillators.push(Oscillator::from_stop(
-            stop,
-            self.frequency,
-            self.sample_rate,
-        ));
+    /// Moves `pipes` into the note as far as its capacity allows.
+    pub fn add_pipes(
+        &mut self,
+        channel: u8,
+        pipes: &mut Vec<Oscillator>,
+        queues: &mut AudioQueues,
+    ) {
+        let room = self.oscillators.capacity() - self.oscillators.len();
+        if pipes.len() > room {
+            queues.log(LogEvent::PipesDropped {
+                channel,
+                key: self.key,
+                count: pipes.len() - room,
+            });
+            while pipes.len() > room {
+                queues.retire(Retired::Pipe(pipes.pop().unwrap()));
+            }
+        }
+        self.oscillators.append(pipes);
     }
 
-    pub fn remove_stop(&mut self, stop: &Stop) {
+    /// Releases `ranks` sounding pipes of `stop`.
+    pub fn remove_stop(&mut self, stop: StopId, mut ranks: usize) {
         for oscillator in &mut self.oscillators {
-            if oscillator.matches_stop(stop, self.frequency) && !oscillator.is_released {
+            if ranks > 0 && oscillator.matches_stop(stop) && !oscillator.is_released {
                 oscillator.release();
-                return;
+                ranks -= 1;
             }
         }
     }
@@ -56,14 +70,33 @@ impl Note {
             .for_each(|oscillator| oscillator.release());
     }
 
+    /// Hands pipes that have died away back to the control thread.
+    pub fn retire_finished(&mut self, queues: &mut AudioQueues) {
+        let mut index = 0;
+        while index < self.oscillators.len() {
+            if self.oscillators[index].is_finished() {
+                queues.retire(Retired::Pipe(self.oscillators.swap_remove(index)));
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    pub fn wind_demand(&self) -> f32 {
+        self.oscillators.iter().map(|osc| osc.wind_demand()).sum()
+    }
+
+    /// A held note stays, even when no stop is drawn, so stops drawn later
+    /// sound on it.
     pub fn is_finished(&self) -> bool {
-        self.oscillators.iter().all(|osc| osc.is_finished())
+        self.is_released && self.oscillators.is_empty()
     }
 
-    pub fn next_sample(&mut self) -> f32 {
-        self.oscillators
-            .iter_mut()
-            .map(|osc| osc.next_sample())
-            .sum()
+    /// Adds the note's pipes into `left` and `right`. `pitch` holds a
+    /// per-sample frequency ratio applied to every pipe, e.g. from a tremulant.
+    pub fn render(&mut self, pitch: &[f32], left: &mut [f32], right: &mut [f32]) {
+        for oscillator in self.oscillators.iter_mut() {
+            oscillator.render(pitch, left, right);
+        }
     }
 }
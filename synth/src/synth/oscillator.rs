@@ -1,77 +1,219 @@
-use super::{stop::Stop, waveform::Waveform};
+use super::synth::BLOCK_SIZE;
+use super::{
+    envelope::Envelope,
+    pan,
+    sample::SamplePlayer,
+    stop::{Stop, StopId},
+    transient::Transient,
+    waveform::{Antialiasing, Waveform},
+    wind,
+};
 
 // TODO CLEAN UP THIS FILE!
 
 pub struct Oscillator {
     phase: f32,
     pub frequency: f32,
+    /// The stop this pipe belongs to, so drawing it off finds its pipes.
+    stop: StopId,
     sample_rate: f32,
     envelope: Envelope,
     waveform: Waveform,
+    antialiasing: Antialiasing,
+    /// See `Waveform::organ_blend`.
+    organ_blend: f32,
     amp: f32,
+    gains: (f32, f32),
+    transient: Option<Transient>,
+    sampler: Option<SamplePlayer>,
+    wind_demand: f32,
     pub is_released: bool,
 }
 
-// TODO is this good?
-fn detune(frequency: f32) -> f32 {
-    // let randfreq: f32 = (rand::random::<f32>() - 0.5) * 2.0;
-    // frequency + randfreq
-    frequency
-}
-
 impl Oscillator {
-    pub fn from_stop(stop: &Stop, frequency: f32, sample_rate: f32) -> Self {
-        Self::new(
-            frequency * stop.frequency_ratio,
-            sample_rate,
-            stop.waveform,
-            stop.amplitude_ratio,
-        )
+    /// One oscillator per rank the stop sounds at `key`; none outside its
+    /// compass.
+    pub fn from_stop(
+        stop: &Stop,
+        key: u8,
+        frequency: f32,
+        sample_rate: f32,
+    ) -> impl Iterator<Item = Self> + '_ {
+        let pipe_key = stop.pipe_key(key);
+        // Folded keys borrow the pipes of another octave.
+        let frequency = match pipe_key {
+            Some(pipe_key) => frequency * 2.0f32.powf((pipe_key as f32 - key as f32) / 12.0),
+            None => frequency,
+        };
+        let key = pipe_key.unwrap_or(key);
+        let pan = stop.pan_layout.position(key, stop.pan, stop.pan_spread);
+        pipe_key
+            .map(|key| stop.ranks(key))
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(move |(rank, (ratio, detune))| {
+                let cents = detune + random_detune(stop, key, rank);
+                Self::new(
+                    frequency * stop.frequency_ratio * ratio * 2.0f32.powf(cents / 1200.0),
+                    sample_rate,
+                    stop,
+                    pan,
+                )
+            })
     }
 
-    pub fn new(frequency: f32, sample_rate: f32, waveform: Waveform, amp: f32) -> Self {
-        println!(
-            "Oscillator::new({}, {}, {})",
-            frequency,
-            amp,
-            waveform.str()
-        );
+    pub fn new(frequency: f32, sample_rate: f32, stop: &Stop, pan: f32) -> Self {
         Self {
             phase: 0.0, //rand::random(),
-            frequency: detune(frequency),
+            frequency,
+            stop: stop.id,
             sample_rate,
-            envelope: Envelope::new(sample_rate, frequency),
-            waveform,
-            amp: amp * iso_equal_loudness(frequency),
+            envelope: Envelope::new(sample_rate, stop.envelope.at_frequency(frequency)),
+            waveform: stop.waveform.clone(),
+            antialiasing: stop.antialiasing,
+            organ_blend: Waveform::organ_blend(frequency),
+            amp: amplitude(stop, frequency),
+            gains: pan::gains(pan),
+            transient: stop
+                .transient
+                .map(|transient| Transient::new(&transient, frequency, sample_rate)),
+            sampler: stop
+                .samples
+                .as_ref()
+                .map(|samples| SamplePlayer::new(samples, frequency, sample_rate)),
+            wind_demand: wind::pipe_demand(stop.amplitude_ratio, frequency),
             is_released: false,
         }
     }
 
-    pub fn next_sample(&mut self) -> f32 {
-        self.advance_phase();
-        let wave = self.waveform.generate_sample(self.phase, self.frequency);
-        wave * self.amp * self.envelope.next()
+    /// Adds the next `pitch.len()` samples into `left` and `right`. `pitch`
+    /// is a per-sample frequency ratio, e.g. from a tremulant.
+    pub fn render(&mut self, pitch: &[f32], left: &mut [f32], right: &mut [f32]) {
+        let len = pitch.len();
+        let mut pitch_buffer = [0.0; BLOCK_SIZE];
+        let mut gain = [1.0; BLOCK_SIZE];
+        let mut noise = [0.0; BLOCK_SIZE];
+        let mut wave = [0.0; BLOCK_SIZE];
+        let mut envelope = [0.0; BLOCK_SIZE];
+        let (pitch_buffer, gain, noise, wave, envelope) = (
+            &mut pitch_buffer[..len],
+            &mut gain[..len],
+            &mut noise[..len],
+            &mut wave[..len],
+            &mut envelope[..len],
+        );
+        pitch_buffer.copy_from_slice(pitch);
+
+        if let Some(transient) = &mut self.transient {
+            for ((pitch, gain), noise) in pitch_buffer
+                .iter_mut()
+                .zip(gain.iter_mut())
+                .zip(noise.iter_mut())
+            {
+                let (transient_noise, transient_pitch, transient_gain) = transient.next();
+                *pitch *= transient_pitch;
+                *gain = transient_gain;
+                *noise = transient_noise;
+            }
+            if transient.is_finished() {
+                self.transient = None;
+            }
+        }
+
+        match &mut self.sampler {
+            Some(sampler) => {
+                for (wave, &pitch) in wave.iter_mut().zip(pitch_buffer.iter()) {
+                    *wave = sampler.next(pitch);
+                }
+            }
+            None => {
+                // Phases first, as each depends on the last; the waveform
+                // then runs over the whole block.
+                let phase_increment = self.frequency / self.sample_rate;
+                let mut phases = [0.0; BLOCK_SIZE];
+                let phases = &mut phases[..len];
+                for (phase, &pitch) in phases.iter_mut().zip(pitch_buffer.iter()) {
+                    self.phase += phase_increment * pitch;
+                    if self.phase >= 1.0 {
+                        self.phase -= 1.0;
+                    }
+                    *phase = self.phase;
+                }
+                self.waveform.render(
+                    self.antialiasing,
+                    phases,
+                    phase_increment,
+                    self.organ_blend,
+                    wave,
+                );
+            }
+        }
+
+        self.envelope.render(envelope);
+        // Straight-line arithmetic over whole buffers, which vectorizes.
+        let (left_gain, right_gain) = (self.gains.0 * self.amp, self.gains.1 * self.amp);
+        let samples = wave
+            .iter()
+            .zip(gain.iter())
+            .zip(envelope.iter())
+            .zip(noise.iter())
+            .map(|(((wave, gain), envelope), noise)| wave * gain * envelope + noise);
+        for ((left, right), sample) in left.iter_mut().zip(right.iter_mut()).zip(samples) {
+            *left += sample * left_gain;
+            *right += sample * right_gain;
+        }
     }
 
     pub fn release(&mut self) {
-        self.envelope.trigger_release();
+        match &mut self.sampler {
+            Some(sampler) => sampler.release(),
+            None => self.envelope.trigger_release(),
+        }
         self.is_released = true;
     }
 
+    /// Wind currently drawn by this pipe, following its envelope.
+    pub fn wind_demand(&self) -> f32 {
+        self.wind_demand * self.envelope.value
+    }
+
     pub fn is_finished(&self) -> bool {
-        self.envelope.is_finished()
+        match &self.sampler {
+            Some(sampler) => sampler.is_finished(),
+            None => self.envelope.is_finished(),
+        }
+    }
+
+    pub fn matches_stop(&self, stop: StopId) -> bool {
+        self.stop == stop
     }
+}
 
-    // TODO maybe clean this?
-    pub fn matches_stop(&self, stop: &Stop, frequency: f32) -> bool {
-        self.frequency == frequency * stop.frequency_ratio
-            && self.waveform == stop.waveform
-            && self.amp == stop.amplitude_ratio * iso_equal_loudness(frequency)
+/// A fixed tuning error in cents for this stop's pipe at `key`, uniform in
+/// ±`random_detune`. Hashing rather than `rand` keeps it stable across notes.
+fn random_detune(stop: &Stop, key: u8, rank: usize) -> f32 {
+    if stop.random_detune == 0.0 {
+        return 0.0;
     }
+    let mut hash = stop.frequency_ratio.to_bits()
+        ^ stop.amplitude_ratio.to_bits().rotate_left(16)
+        ^ ((key as u32) << 8)
+        ^ rank as u32;
+    // Murmur3's finalizer, to spread nearby keys across the range.
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+    (hash as f32 / u32::MAX as f32 * 2.0 - 1.0) * stop.random_detune
+}
 
-    fn advance_phase(&mut self) {
-        self.phase += self.frequency / self.sample_rate;
-        self.phase %= 1.0;
+/// Recorded pipes already have their natural loudness across the compass.
+fn amplitude(stop: &Stop, frequency: f32) -> f32 {
+    match stop.samples {
+        Some(_) => stop.amplitude_ratio,
+        None => stop.amplitude_ratio * iso_equal_loudness(frequency),
     }
 }
 
@@ -109,89 +251,3 @@ fn iso_equal_loudness(frequency: f32) -> f32 {
     // gain
     // 1.0
 }
-
-pub struct Envelope {
-    pub value: f32,
-    attack: f32,
-    decay: f32,
-    release: f32,
-    pub state: EnvelopeState,
-    pub sample_rate: f32,
-}
-
-enum EnvelopeState {
-    Attack,
-    Decay,
-    Sustain,
-    Release,
-    Idle,
-}
-
-const EPSILON: f32 = 1e-6;
-
-impl Envelope {
-    pub fn new(sample_rate: f32, frequency: f32) -> Self {
-        Self {
-            value: 1e-6,  // Start at a very low value to avoid clicks
-            attack: 0.05, // 10ms attack
-            decay: 0.0,
-            release: 0.1, // / (frequency / 400.0), // 100ms release
-            state: EnvelopeState::Attack,
-            sample_rate,
-        }
-    }
-
-    pub fn next(&mut self) -> f32 {
-        // return 1.0;
-        match self.state {
-            EnvelopeState::Attack => {
-                self.value += 1.0 / (self.attack * self.sample_rate);
-                if self.value >= 1.0 {
-                    self.value = 1.0;
-                    self.state = EnvelopeState::Sustain;
-                }
-
-                // self.value *= 1.1;  // Exponential decay (0.995 ~ 0.2s)
-                // if self.value >= 1.0 {
-                //     self.value = 1.0;
-                //     self.state = EnvelopeState::Sustain;
-                // }
-            }
-            EnvelopeState::Release => {
-                self.value -= 1.0 / (self.release * self.sample_rate);
-                if self.value <= 0.0 {
-                    self.value = 0.0;
-                    // self.state = EnvelopeState::Idle;
-                }
-
-                // self.value *= 0.995;  // Exponential decay (0.995 ~ 0.2s)
-                // if self.value < 0.001 {
-                //     self.value = 0.0;
-                //     self.state = EnvelopeState::Idle;
-                // }
-            }
-            EnvelopeState::Decay => {
-                self.value -= 1.0 / (self.decay * self.sample_rate);
-                if self.value <= 0.0 {
-                    self.value = 0.0;
-                    self.state = EnvelopeState::Sustain;
-                    // TODO where to decay to?
-                }
-            }
-            _ => {}
-        }
-        self.value
-    }
-
-    pub fn is_finished(&self) -> bool {
-        if let EnvelopeState::Release = self.state {
-            self.value <= EPSILON
-        } else {
-            false
-        }
-    }
-
-    pub fn trigger_release(&mut self) {
-        self.state = EnvelopeState::Release;
-    }
-}
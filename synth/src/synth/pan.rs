@@ -0,0 +1,51 @@
+/// How the pipes of a rank are spread across the stereo field, mirroring the
+/// way they stand on the chest.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PanLayout {
+    /// Every pipe sits at the stop's pan position.
+    Center,
+    /// C and C# chests: alternate semitones go to opposite sides.
+    Alternate,
+    /// Bass on the left rising to treble on the right.
+    Chromatic,
+    /// Alternating sides with the largest pipes outermost, as in pedal towers
+    /// on the flanks of the case.
+    Towers,
+}
+
+const LOWEST_KEY: f32 = 24.0;
+const HIGHEST_KEY: f32 = 108.0;
+
+impl PanLayout {
+    pub fn parse(layout: &str) -> Self {
+        match layout {
+            "alternate" => PanLayout::Alternate,
+            "chromatic" => PanLayout::Chromatic,
+            "towers" => PanLayout::Towers,
+            _ => PanLayout::Center,
+        }
+    }
+
+    /// Pan position of `key` in [-1, 1], where -1 is hard left.
+    pub fn position(&self, key: u8, pan: f32, spread: f32) -> f32 {
+        let side = if key.is_multiple_of(2) { -1.0 } else { 1.0 };
+        let height = ((key as f32 - LOWEST_KEY) / (HIGHEST_KEY - LOWEST_KEY)).clamp(0.0, 1.0);
+        let offset = match self {
+            PanLayout::Center => 0.0,
+            PanLayout::Alternate => side * spread,
+            PanLayout::Chromatic => (2.0 * height - 1.0) * spread,
+            PanLayout::Towers => side * spread * (1.0 - height),
+        };
+        (pan + offset).clamp(-1.0, 1.0)
+    }
+}
+
+/// Constant-power left/right gains for a pan position, normalized so a
+/// centered source keeps the level it had on the old mono output.
+pub fn gains(position: f32) -> (f32, f32) {
+    let angle = (position + 1.0) * std::f32::consts::FRAC_PI_4;
+    (
+        angle.cos() * std::f32::consts::SQRT_2,
+        angle.sin() * std::f32::consts::SQRT_2,
+    )
+}
@@ -0,0 +1,266 @@
+use super::note::Note;
+use super::oscillator::Oscillator;
+use super::stop::StopId;
+use super::thingy::InternalSynth;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::fmt;
+
+const COMMAND_CAPACITY: usize = 1024;
+const RETIRED_CAPACITY: usize = 1024;
+const LOG_CAPACITY: usize = 256;
+
+/// A change to the audio thread's state, prepared on the control thread so
+/// applying it never allocates. Notes are addressed by the id the control
+/// thread gave them; commands for notes that are already gone hand their
+/// payload straight back.
+pub enum Command {
+    /// Starts using a division on `channel`.
+    AddDivision {
+        channel: u8,
+        division: Box<InternalSynth>,
+    },
+    NoteOn {
+        channel: u8,
+        note: Note,
+    },
+    NoteOff {
+        channel: u8,
+        id: u64,
+    },
+    /// Replaces the pipes of a held note, e.g. after a preset change.
+    SetPipes {
+        channel: u8,
+        id: u64,
+        pipes: Vec<Oscillator>,
+    },
+    AddPipes {
+        channel: u8,
+        id: u64,
+        pipes: Vec<Oscillator>,
+    },
+    /// Releases the `ranks` pipes `stop` sounds in a held note.
+    RemoveStop {
+        channel: u8,
+        id: u64,
+        stop: StopId,
+        ranks: usize,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    /// Releases every note, which then dies away as usual.
+    AllNotesOff {
+        channel: u8,
+    },
+    /// Cuts every note and effect tail at once.
+    AllSoundOff {
+        channel: u8,
+    },
+    /// Returns tremulant, wind and swell to their configured state.
+    ResetControllers {
+        channel: u8,
+    },
+    /// All sound off and controllers reset on every division, and the
+    /// master effects cleared.
+    Panic,
+}
+
+/// Something the audio thread is done with. It goes back to the control
+/// thread to be freed or reused, as dropping it would free memory.
+// Boxing the pipe would mean allocating on the audio thread; the queue's
+// slots are allocated once, so their size only costs memory.
+#[allow(clippy::large_enum_variant)]
+pub enum Retired {
+    Note(Note),
+    Pipe(Oscillator),
+    Pipes(Vec<Oscillator>),
+}
+
+/// What the audio thread has to say, printed by the control thread.
+#[derive(Debug, Clone, Copy)]
+pub enum LogEvent {
+    VoiceStolen {
+        channel: u8,
+        key: u8,
+    },
+    PipesDropped {
+        channel: u8,
+        key: u8,
+        count: usize,
+    },
+    RetiredQueueFull,
+    /// Commands that arrived after the frame they were scheduled for, and
+    /// the most frames any of them was late by.
+    LateCommands {
+        count: usize,
+        frames: u64,
+    },
+}
+
+impl fmt::Display for LogEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogEvent::VoiceStolen { channel, key } => {
+                write!(
+                    f,
+                    "Voice limit reached on channel {}, stole key {}",
+                    channel + 1,
+                    key
+                )
+            }
+            LogEvent::PipesDropped {
+                channel,
+                key,
+                count,
+            } => write!(
+                f,
+                "Pipe limit reached on channel {} key {}, dropped {} pipes",
+                channel + 1,
+                key,
+                count
+            ),
+            LogEvent::RetiredQueueFull => {
+                write!(f, "Retired queue full, freeing on the audio thread")
+            }
+            LogEvent::LateCommands { count, frames } => write!(
+                f,
+                "{} commands applied late, by up to {} frames",
+                count, frames
+            ),
+        }
+    }
+}
+
+/// A command and the frame from which it applies.
+struct Scheduled {
+    frame: u64,
+    command: Command,
+}
+
+/// The audio thread's ends of the queues.
+pub struct AudioQueues {
+    commands: Consumer<Scheduled>,
+    retired: Producer<Retired>,
+    log: Producer<LogEvent>,
+    /// Late commands since the last `report_late`.
+    late: usize,
+    latest_by: u64,
+}
+
+/// The control thread's ends of the queues.
+pub struct ControlQueues {
+    commands: Producer<Scheduled>,
+    retired: Consumer<Retired>,
+    log: Consumer<LogEvent>,
+}
+
+pub fn queues() -> (ControlQueues, AudioQueues) {
+    let (command_tx, command_rx) = RingBuffer::new(COMMAND_CAPACITY);
+    let (retired_tx, retired_rx) = RingBuffer::new(RETIRED_CAPACITY);
+    let (log_tx, log_rx) = RingBuffer::new(LOG_CAPACITY);
+    (
+        ControlQueues {
+            commands: command_tx,
+            retired: retired_rx,
+            log: log_rx,
+        },
+        AudioQueues {
+            commands: command_rx,
+            retired: retired_tx,
+            log: log_tx,
+            late: 0,
+            latest_by: 0,
+        },
+    )
+}
+
+impl AudioQueues {
+    /// The next command due by `frame`, if any. Commands for frame 0 are
+    /// meant for straight away; any other past its frame is counted late.
+    pub fn next_command(&mut self, frame: u64) -> Option<Command> {
+        if self.next_command_frame()? > frame {
+            return None;
+        }
+        let scheduled = self.commands.pop().ok()?;
+        if scheduled.frame != 0 && scheduled.frame < frame {
+            self.late += 1;
+            self.latest_by = self.latest_by.max(frame - scheduled.frame);
+        }
+        Some(scheduled.command)
+    }
+
+    /// Logs the commands applied late since the last call, if there were any.
+    pub fn report_late(&mut self) {
+        if self.late > 0 {
+            self.log(LogEvent::LateCommands {
+                count: self.late,
+                frames: self.latest_by,
+            });
+            self.late = 0;
+            self.latest_by = 0;
+        }
+    }
+
+    /// When the next command is due; commands apply in the order sent.
+    pub fn next_command_frame(&self) -> Option<u64> {
+        self.commands.peek().ok().map(|scheduled| scheduled.frame)
+    }
+
+    pub fn retire(&mut self, retired: Retired) {
+        if self.retired.push(retired).is_err() {
+            // Only if the control thread has stalled; freeing here beats
+            // leaking.
+            self.log(LogEvent::RetiredQueueFull);
+        }
+    }
+
+    /// Drops the event if the log queue is full; it is only diagnostics.
+    pub fn log(&mut self, event: LogEvent) {
+        let _ = self.log.push(event);
+    }
+}
+
+impl ControlQueues {
+    /// Queues `command` to apply once the synth reaches `frame`.
+    pub fn send(&mut self, frame: u64, command: Command) {
+        if self.commands.push(Scheduled { frame, command }).is_err() {
+            println!("Command queue full, dropping a command");
+        }
+    }
+
+    pub fn next_retired(&mut self) -> Option<Retired> {
+        self.retired.pop().ok()
+    }
+
+    pub fn next_log(&mut self) -> Option<LogEvent> {
+        self.log.pop().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_commands_applied_after_their_frame() {
+        let (mut control, mut audio) = queues();
+        control.send(0, Command::Panic);
+        control.send(100, Command::Panic);
+        control.send(120, Command::Panic);
+        control.send(200, Command::Panic);
+        while audio.next_command(150).is_some() {}
+        audio.report_late();
+        match control.next_log() {
+            Some(LogEvent::LateCommands { count, frames }) => {
+                assert_eq!((count, frames), (2, 50));
+            }
+            other => panic!("Expected late commands, got {:?}", other),
+        }
+        assert!(audio.next_command(199).is_none());
+        assert!(audio.next_command(200).is_some());
+        audio.report_late();
+        assert!(control.next_log().is_none());
+    }
+}
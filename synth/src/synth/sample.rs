@@ -0,0 +1,306 @@
+use super::wav::Wav;
+use crate::config::{SampleNoteConfig, SamplesConfig};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+/// Crossfade from the sustain into the release tail, in seconds.
+const RELEASE_CROSSFADE: f32 = 0.01;
+/// Fade used on release when a sample has no recorded tail.
+const RELEASE_FADE: f32 = 0.05;
+
+/// Decoded audio shared by every stop and voice that uses the same file.
+pub struct SampleData {
+    sample_rate: f32,
+    /// Mono; stereo files are mixed down and placed with the stop's pan.
+    frames: Vec<f32>,
+}
+
+/// The recorded pipes of a sampled stop.
+#[derive(Debug)]
+pub struct SampleSet {
+    zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    /// Frequency the pipe was recorded at.
+    pitch: f32,
+    file: Arc<SampleFile>,
+    loop_range: Option<(usize, usize)>,
+    release: Release,
+}
+
+#[derive(Debug)]
+enum Release {
+    /// Tail stored in its own file.
+    File(Arc<SampleFile>),
+    /// Tail stored in the attack file, starting at this frame.
+    From(usize),
+    None,
+}
+
+/// A WAV file loaded up front, or in the background from first use when
+/// loading lazily.
+struct SampleFile {
+    path: PathBuf,
+    data: OnceLock<Option<Arc<SampleData>>>,
+    /// Set once a background load has started.
+    requested: AtomicBool,
+}
+
+impl SampleSet {
+    pub fn new(config: &SamplesConfig) -> Self {
+        let directory = Path::new(&config.directory);
+        let zones: Vec<Zone> = config
+            .notes
+            .iter()
+            .map(|note| Zone::new(note, directory))
+            .collect();
+        if !config.lazy_load {
+            for zone in &zones {
+                zone.file.get();
+                if let Release::File(file) = &zone.release {
+                    file.get();
+                }
+            }
+        }
+        Self { zones }
+    }
+
+    /// Picks the loaded recording closest in pitch to `frequency`. If a
+    /// closer one is still on disk it starts loading for later notes, so
+    /// note-on never waits for a file.
+    fn zone(&self, frequency: f32) -> Option<&Zone> {
+        let distance = |zone: &&Zone| (frequency / zone.pitch).log2().abs();
+        let closest = self
+            .zones
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))?;
+        if closest.file.ready().is_some() {
+            return Some(closest);
+        }
+        closest.load_in_background();
+        self.zones
+            .iter()
+            .filter(|zone| zone.file.ready().is_some())
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+    }
+}
+
+impl Zone {
+    fn load_in_background(&self) {
+        self.file.load_in_background();
+        if let Release::File(file) = &self.release {
+            file.load_in_background();
+        }
+    }
+
+    fn new(config: &SampleNoteConfig, directory: &Path) -> Self {
+        let loop_range = match (config.loop_start, config.loop_end) {
+            (Some(start), Some(end)) if end > start => Some((start, end)),
+            _ => None,
+        };
+        let release = match (&config.release, config.release_start) {
+            (Some(file), _) => Release::File(SampleFile::new(directory.join(file))),
+            (None, Some(start)) => Release::From(start),
+            (None, None) => match loop_range {
+                Some((_, end)) => Release::From(end),
+                None => Release::None,
+            },
+        };
+        Self {
+            pitch: config
+                .pitch
+                .unwrap_or_else(|| 440.0 * 2.0f32.powf((config.key as f32 - 69.0) / 12.0)),
+            file: SampleFile::new(directory.join(&config.file)),
+            loop_range,
+            release,
+        }
+    }
+}
+
+impl SampleFile {
+    fn new(path: PathBuf) -> Arc<Self> {
+        Arc::new(Self {
+            path,
+            data: OnceLock::new(),
+            requested: AtomicBool::new(false),
+        })
+    }
+
+    /// Loads the file if it isn't yet, waiting for it.
+    fn get(&self) -> Option<Arc<SampleData>> {
+        self.data.get_or_init(|| load_cached(&self.path)).clone()
+    }
+
+    /// The file's audio if it has finished loading.
+    fn ready(&self) -> Option<Arc<SampleData>> {
+        self.data.get().cloned().flatten()
+    }
+
+    fn load_in_background(self: &Arc<Self>) {
+        if self.requested.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let file = self.clone();
+        thread::spawn(move || file.get());
+    }
+}
+
+impl std::fmt::Debug for SampleFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SampleFile").field(&self.path).finish()
+    }
+}
+
+/// Loads each file once per process, however many stops refer to it.
+fn load_cached(path: &Path) -> Option<Arc<SampleData>> {
+    static LOADED: OnceLock<Mutex<HashMap<PathBuf, Arc<SampleData>>>> = OnceLock::new();
+    let mut loaded = LOADED.get_or_init(Default::default).lock().unwrap();
+    if let Some(data) = loaded.get(path) {
+        return Some(data.clone());
+    }
+    match load(path) {
+        Ok(data) => {
+            let data = Arc::new(data);
+            loaded.insert(path.to_path_buf(), data.clone());
+            Some(data)
+        }
+        Err(e) => {
+            println!("Error loading sample {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<SampleData, hound::Error> {
+    let wav = Wav::load(path)?;
+    let scale = 1.0 / wav.channels.len() as f32;
+    let mut frames = vec![0.0; wav.channels[0].len()];
+    for channel in &wav.channels {
+        for (frame, sample) in frames.iter_mut().zip(channel) {
+            *frame += sample * scale;
+        }
+    }
+    Ok(SampleData {
+        sample_rate: wav.sample_rate,
+        frames,
+    })
+}
+
+/// Plays one recorded pipe: the attack, then the sustain loop until
+/// released, then the release tail.
+pub struct SamplePlayer {
+    attack: Option<Arc<SampleData>>,
+    loop_range: Option<(usize, usize)>,
+    position: f64,
+    /// Playback rate before any pitch modulation.
+    rate: f64,
+    release: Option<(Arc<SampleData>, usize)>,
+    release_position: f64,
+    released: bool,
+    /// Level of the sustain part, fading out after release.
+    sustain_level: f32,
+    fade_step: f32,
+}
+
+impl SamplePlayer {
+    pub fn new(samples: &SampleSet, frequency: f32, sample_rate: f32) -> Self {
+        let zone = samples.zone(frequency);
+        let attack = zone.and_then(|zone| zone.file.ready());
+        let rate = match (zone, &attack) {
+            (Some(zone), Some(data)) => {
+                (frequency / zone.pitch) as f64 * (data.sample_rate / sample_rate) as f64
+            }
+            _ => 1.0,
+        };
+        let release = zone.and_then(|zone| match &zone.release {
+            Release::File(file) => file.ready().map(|data| (data, 0)),
+            Release::From(start) => attack.clone().map(|data| (data, *start)),
+            Release::None => None,
+        });
+        let fade_time = if release.is_some() {
+            RELEASE_CROSSFADE
+        } else {
+            RELEASE_FADE
+        };
+        Self {
+            loop_range: zone.and_then(|zone| zone.loop_range),
+            attack,
+            position: 0.0,
+            rate,
+            release_position: release.as_ref().map_or(0.0, |(_, start)| *start as f64),
+            release,
+            released: false,
+            sustain_level: 1.0,
+            fade_step: 1.0 / (fade_time * sample_rate),
+        }
+    }
+
+    /// `pitch` is a frequency ratio, e.g. from a tremulant or wind sag.
+    pub fn next(&mut self, pitch: f32) -> f32 {
+        let rate = self.rate * pitch as f64;
+        let mut output = 0.0;
+        if self.sustain_level > 0.0 {
+            if let Some(data) = &self.attack {
+                output += interpolate(&data.frames, self.position) * self.sustain_level;
+            }
+            self.position += rate;
+            if let Some((start, end)) = self.loop_range {
+                if self.position >= end as f64 {
+                    self.position -= (end - start) as f64;
+                }
+            }
+            if self.released {
+                self.sustain_level = (self.sustain_level - self.fade_step).max(0.0);
+            }
+        }
+        if self.released {
+            if let Some((data, _)) = &self.release {
+                output += interpolate(&data.frames, self.release_position);
+                self.release_position += rate;
+            }
+        }
+        output
+    }
+
+    pub fn release(&mut self) {
+        self.released = true;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        let sustain_done = match &self.attack {
+            Some(data) => {
+                self.sustain_level <= 0.0
+                    || (self.loop_range.is_none() && self.position >= data.frames.len() as f64)
+            }
+            None => true,
+        };
+        let tail_done = match &self.release {
+            Some((data, _)) if self.released => self.release_position >= data.frames.len() as f64,
+            _ => true,
+        };
+        sustain_done && tail_done
+    }
+}
+
+/// 4-point Hermite interpolation; reads past either end as silence.
+fn interpolate(frames: &[f32], position: f64) -> f32 {
+    let index = position.floor() as isize;
+    let t = (position - index as f64) as f32;
+    let at = |i: isize| {
+        if i < 0 {
+            0.0
+        } else {
+            frames.get(i as usize).copied().unwrap_or(0.0)
+        }
+    };
+    let (y0, y1, y2, y3) = (at(index - 1), at(index), at(index + 1), at(index + 2));
+    let c1 = 0.5 * (y2 - y0);
+    let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+    let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+    ((c3 * t + c2) * t + c1) * t + y1
+}
@@ -1,19 +1,176 @@
-use super::waveform::Waveform;
+use super::compass::Compass;
+use super::envelope::EnvelopeSettings;
+use super::pan::PanLayout;
+use super::sample::SampleSet;
+use super::transient::TransientSettings;
+use super::waveform::{Antialiasing, Waveform};
 use crate::config::StopConfig;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// Identifies a stop cheaply, so the audio thread can find its pipes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StopId(u32);
+
+impl StopId {
+    fn next() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Copies of a stop share its id; each newly built one gets its own.
+#[derive(Debug, Clone)]
 pub struct Stop {
+    pub id: StopId,
     pub waveform: Waveform,
+    pub antialiasing: Antialiasing,
     pub frequency_ratio: f32,
     pub amplitude_ratio: f32,
+    pub envelope: EnvelopeSettings,
+    pub transient: Option<TransientSettings>,
+    /// Recorded pipes; when present they replace the waveform.
+    pub samples: Option<Arc<SampleSet>>,
+    /// Offset in cents, e.g. for a Voix céleste beating against the unison.
+    pub detune: f32,
+    /// Sounds an undetuned rank alongside the detuned one, so a single stop
+    /// undulates on its own.
+    pub detune_paired: bool,
+    /// Largest per-pipe tuning error in cents. Each key always gets the
+    /// same error, as a real pipe would.
+    pub random_detune: f32,
+    pub pan: f32,
+    pub pan_layout: PanLayout,
+    pub pan_spread: f32,
+    /// Ranks of a compound stop, by the key from which they apply. Empty
+    /// for a single-rank stop.
+    pub composition: Vec<Break>,
+    pub compass: Option<Compass>,
+}
+
+/// Where a mixture "breaks back": from `from_key` upwards the stop sounds
+/// one rank per ratio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Break {
+    pub from_key: u8,
+    pub ratios: Vec<f32>,
+}
+
+impl PartialEq for Stop {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
 }
 
 impl Stop {
     pub fn new(config: &StopConfig) -> Self {
         Self {
-            waveform: Waveform::parse(&config.waveform),
+            id: StopId::next(),
+            waveform: match &config.harmonics {
+                Some(harmonics) => {
+                    Waveform::additive(harmonics, config.phases.as_deref().unwrap_or(&[]))
+                }
+                None => Waveform::parse(&config.waveform),
+            },
+            antialiasing: Antialiasing::parse(config.antialiasing.as_deref().unwrap_or("polyblep")),
             frequency_ratio: config.frequency_ratio,
             amplitude_ratio: config.amplitude_ratio,
+            envelope: match (&config.envelope, &config.samples) {
+                (Some(envelope), _) => EnvelopeSettings::new(envelope),
+                // Recordings carry their own attack and release.
+                (None, Some(_)) => EnvelopeSettings {
+                    attack: 0.0,
+                    ..Default::default()
+                },
+                (None, None) => EnvelopeSettings::default(),
+            },
+            transient: config.chiff.as_ref().map(TransientSettings::new),
+            samples: config
+                .samples
+                .as_ref()
+                .map(|samples| Arc::new(SampleSet::new(samples))),
+            detune: config.detune,
+            detune_paired: config.detune_paired,
+            random_detune: config.random_detune.abs(),
+            pan: config.pan,
+            pan_layout: PanLayout::parse(config.pan_layout.as_deref().unwrap_or("center")),
+            pan_spread: config.pan_spread,
+            composition: config
+                .composition
+                .as_ref()
+                .map(|breaks| {
+                    let mut breaks: Vec<Break> = breaks
+                        .iter()
+                        .map(|config| Break {
+                            from_key: config.from,
+                            ratios: config.ratios.clone(),
+                        })
+                        .collect();
+                    breaks.sort_by_key(|b| b.from_key);
+                    breaks
+                })
+                .unwrap_or_default(),
+            compass: config.compass.as_ref().map(Compass::new),
+        }
+    }
+
+    /// The key whose pipes sound when `key` is played, or `None` if the
+    /// stop is silent there.
+    pub fn pipe_key(&self, key: u8) -> Option<u8> {
+        match &self.compass {
+            Some(compass) => compass.map(key),
+            None => Some(key),
+        }
+    }
+
+    /// Number of pipes sounding when `key` is played.
+    pub fn pipe_count(&self, key: u8) -> usize {
+        self.pipe_key(key).map_or(0, |key| self.ranks(key).len())
+    }
+
+    /// The (frequency ratio, detune in cents) of every pipe this stop sounds
+    /// at `key`, on top of `frequency_ratio`. A paired stop's undetuned rank
+    /// comes first.
+    pub fn ranks(&self, key: u8) -> Vec<(f32, f32)> {
+        let ratios = match self.composition.iter().rev().find(|b| b.from_key <= key) {
+            Some(b) => &b.ratios[..],
+            // Keys below the first break use its composition.
+            None => self
+                .composition
+                .first()
+                .map_or(&[1.0][..], |b| &b.ratios[..]),
+        };
+        let detunes: &[f32] = if self.detune_paired {
+            &[0.0, self.detune]
+        } else {
+            &[self.detune]
+        };
+        ratios
+            .iter()
+            .flat_map(|&ratio| detunes.iter().map(move |&detune| (ratio, detune)))
+            .collect()
+    }
+}
+
+impl Default for Stop {
+    fn default() -> Self {
+        Self {
+            id: StopId::next(),
+            waveform: Waveform::Sine,
+            antialiasing: Antialiasing::PolyBlep,
+            frequency_ratio: 1.0,
+            amplitude_ratio: 1.0,
+            envelope: EnvelopeSettings::default(),
+            transient: None,
+            samples: None,
+            detune: 0.0,
+            detune_paired: false,
+            random_detune: 0.0,
+            pan: 0.0,
+            pan_layout: PanLayout::Center,
+            pan_spread: 0.0,
+            composition: Vec::new(),
+            compass: None,
         }
     }
 }
@@ -0,0 +1,106 @@
+use crate::config::SwellConfig;
+use std::f32::consts::TAU;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SwellSettings {
+    pub cc: u8,
+    /// Shade position before any CC arrives, 0 closed to 1 open.
+    pub initial: f32,
+    /// Attenuation in dB with the shades fully closed.
+    pub closed_attenuation: f32,
+    /// Low-pass cutoffs in Hz with the shades closed and open.
+    pub closed_cutoff: f32,
+    pub open_cutoff: f32,
+    /// Seconds for the shades to follow the pedal, avoiding zipper noise.
+    pub smoothing: f32,
+}
+
+impl SwellSettings {
+    pub fn new(config: &SwellConfig) -> Self {
+        Self {
+            cc: config.cc,
+            initial: config.initial.clamp(0.0, 1.0),
+            closed_attenuation: config.closed_attenuation,
+            closed_cutoff: config.closed_cutoff,
+            open_cutoff: config.open_cutoff,
+            smoothing: config.smoothing,
+        }
+    }
+}
+
+/// Swell box around an enclosed division: closing the shades both
+/// attenuates and darkens the sound.
+pub struct Swell {
+    settings: SwellSettings,
+    sample_rate: f32,
+    target: f32,
+    position: f32,
+    smoothing: f32,
+    gain: f32,
+    coefficient: f32,
+    applied_position: f32,
+    state: (f32, f32),
+}
+
+impl Swell {
+    pub fn new(settings: SwellSettings, sample_rate: f32) -> Self {
+        let mut swell = Self {
+            settings,
+            sample_rate,
+            target: settings.initial,
+            position: settings.initial,
+            smoothing: (-1.0 / (settings.smoothing.max(1e-4) * sample_rate)).exp(),
+            gain: 1.0,
+            coefficient: 1.0,
+            applied_position: f32::NAN,
+            state: (0.0, 0.0),
+        };
+        swell.update_coefficients();
+        swell
+    }
+
+    pub fn control_change(&mut self, controller: u8, value: u8) -> bool {
+        if controller != self.settings.cc {
+            return false;
+        }
+        self.target = value as f32 / 127.0;
+        true
+    }
+
+    /// Returns the shades to their initial position.
+    pub fn reset_controllers(&mut self) {
+        self.target = self.settings.initial;
+    }
+
+    /// Clears the filter, keeping the shades where they are.
+    pub fn reset(&mut self) {
+        self.state = (0.0, 0.0);
+    }
+
+    pub fn process_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            (*left, *right) = self.process(*left, *right);
+        }
+    }
+
+    fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        self.position = self.target + (self.position - self.target) * self.smoothing;
+        if (self.position - self.applied_position).abs() > 1e-5 {
+            self.update_coefficients();
+        }
+        self.state.0 += self.coefficient * (left - self.state.0);
+        self.state.1 += self.coefficient * (right - self.state.1);
+        (self.state.0 * self.gain, self.state.1 * self.gain)
+    }
+
+    fn update_coefficients(&mut self) {
+        let closed = 1.0 - self.position;
+        self.gain = 10.0f32.powf(-self.settings.closed_attenuation * closed / 20.0);
+        // Interpolate the cutoff logarithmically so the pedal feels even.
+        let cutoff = self.settings.open_cutoff
+            * (self.settings.closed_cutoff / self.settings.open_cutoff).powf(closed);
+        let cutoff = cutoff.min(self.sample_rate * 0.45);
+        self.coefficient = 1.0 - (-TAU * cutoff / self.sample_rate).exp();
+        self.applied_position = self.position;
+    }
+}
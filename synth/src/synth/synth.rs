@@ -1,144 +1,204 @@
-use super::waveform::Waveform;
-use super::{config, Stop};
+use super::control::{Controller, MidiSender};
+use super::effect::{self, Effect};
+use super::filters::StereoFilter;
+use super::queue::{self, AudioQueues, Command, Retired};
 use crate::config::SynthConfig;
-use crate::midi;
 use crate::synth::thingy::InternalSynth;
-use std::collections::HashMap;
-use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
+
+fn master_chain(sample_rate: f32, config: &SynthConfig) -> Vec<Box<dyn StereoFilter>> {
+    effect::build_chain(&Effect::chain(&config.master.effects), sample_rate)
+}
+
+/// Largest number of frames processed in one pass through the voices.
+/// Scratch buffers of this size live on the stack, so nothing allocates.
+pub const BLOCK_SIZE: usize = 64;
+const MIDI_CHANNELS: usize = 16;
 
 // TODO this file should still be cleaned up a bit
+/// The audio thread's half of the synth. It owns every sounding voice and
+/// only ever hears from the `Controller` through a lock-free queue, so
+/// rendering never blocks or allocates.
 pub struct Synth {
-    midi_tx: mpsc::Sender<[u8; 3]>,
-    synths: Arc<Mutex<HashMap<u8, InternalSynth>>>,
+    /// Indexed by MIDI channel; created by the controller on first use.
+    divisions: [Option<Box<InternalSynth>>; MIDI_CHANNELS],
+    queues: AudioQueues,
+    /// Effects applied to the sum of all divisions.
+    master: Vec<Box<dyn StereoFilter>>,
+    /// Frames rendered so far, the clock commands are scheduled against.
+    frame: u64,
 }
 
 impl Synth {
-    pub fn new(sample_rate: f32, config: SynthConfig) -> Self {
-        let (midi_tx, midi_rx) = mpsc::channel::<[u8; 3]>();
-        let synths = Arc::new(Mutex::new(HashMap::new()));
-        Self::spawn_midi_worker(
-            synths.clone(),
-            midi_rx,
-            &config::get_stops(&config),
-            &config::get_presets(&config),
-            &config::get_preset_defaults(&config),
-            sample_rate,
-        );
-        Self { midi_tx, synths }
+    /// Creates the synth and starts its control thread, which MIDI reaches
+    /// through the returned sender.
+    pub fn new(sample_rate: f32, config: SynthConfig) -> (Self, MidiSender) {
+        let (synth, controller) = Self::new_offline(sample_rate, config);
+        (synth, controller.spawn())
     }
 
-    pub fn next_sample(&mut self) -> f32 {
-        let mut synths = self.synths.lock().unwrap();
-        synths
-            .values_mut()
-            .map(|synth| synth.next_sample())
-            .sum::<f32>()
+    /// Creates the synth without a control thread: MIDI passed to the
+    /// returned controller takes effect at the next `render`, so offline
+    /// renders are deterministic.
+    pub fn new_offline(sample_rate: f32, config: SynthConfig) -> (Self, Controller) {
+        let (control_queues, audio_queues) = queue::queues();
+        let synth = Self {
+            divisions: Default::default(),
+            queues: audio_queues,
+            master: master_chain(sample_rate, &config),
+            frame: 0,
+        };
+        (synth, Controller::new(sample_rate, &config, control_queues))
     }
 
-    pub fn send_midi(&self, midi: [u8; 3]) {
-        self.midi_tx.send(midi).unwrap();
+    /// Frames rendered so far. MIDI scheduled for a later frame through
+    /// `MidiSender::send_at` takes effect exactly there.
+    pub fn frame(&self) -> u64 {
+        self.frame
     }
 
-    fn spawn_midi_worker(
-        synths: Arc<Mutex<HashMap<u8, InternalSynth>>>,
-        midi_rx: mpsc::Receiver<[u8; 3]>,
-        available_stops: &HashMap<u8, Stop>,
-        available_presets: &HashMap<u8, Vec<Stop>>,
-        preset_defaults: &HashMap<u8, Vec<Stop>>,
-        sample_rate: f32,
-    ) {
-        let available_stops = available_stops.clone();
-        let available_presets = available_presets.clone();
-        let preset_defaults = preset_defaults.clone();
-        std::thread::spawn(move || {
-            for midi in midi_rx {
-                match midi::try_parse(&midi) {
-                    Ok(parsed) => {
-                        let mut synths_guard = synths.lock().unwrap();
-                        handle_midi_message(
-                            &mut synths_guard,
-                            &available_presets,
-                            &available_stops,
-                            &preset_defaults,
-                            parsed,
-                            sample_rate,
-                        );
-                    }
-                    Err(e) => println!("Error parsing MIDI message: {:?}", e),
+    /// Renders the next `left.len()` frames into `left` and `right`, which
+    /// must be the same length. Any length works; it is processed internally
+    /// in blocks of at most `BLOCK_SIZE` frames, split wherever a command is
+    /// due.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let mut start = 0;
+        while start < left.len() {
+            let mut end = left.len().min(start + BLOCK_SIZE);
+            // The control thread may push at any moment, so the block is cut
+            // at the same peek that found the next command still ahead.
+            while let Some(due) = self.queues.next_command_frame() {
+                if due > self.frame {
+                    end = end.min(start + (due - self.frame) as usize);
+                    break;
+                }
+                if let Some(command) = self.queues.next_command(self.frame) {
+                    self.apply(command);
                 }
             }
-        });
+            self.render_block(&mut left[start..end], &mut right[start..end]);
+            self.frame += (end - start) as u64;
+            start = end;
+        }
+        self.queues.report_late();
     }
-}
 
-fn handle_midi_message(
-    synths: &mut HashMap<u8, InternalSynth>,
-    presets: &HashMap<u8, Vec<Stop>>,
-    stops: &HashMap<u8, Stop>,
-    preset_defaults: &HashMap<u8, Vec<Stop>>,
-    message: midi::Message,
-    sample_rate: f32,
-) {
-    let synth = get_or_create_synth(synths, message.channel, sample_rate, preset_defaults);
-    match message.kind {
-        midi::MessageKind::NoteOn => handle_note_on(synth, message),
-        midi::MessageKind::NoteOff => handle_note_off(synth, message),
-        midi::MessageKind::ControlChange => handle_control_change(synth, presets, stops, message),
-        _ => {
-            println!("Unhandled MIDI message: {:?}", message);
+    fn render_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        left.fill(0.0);
+        right.fill(0.0);
+        for synth in self.divisions.iter_mut().flatten() {
+            synth.render(left, right, &mut self.queues);
+        }
+        for filter in self.master.iter_mut() {
+            filter.process_block(left, right);
         }
     }
-}
 
-fn get_or_create_synth<'a>(
-    synths: &'a mut HashMap<u8, InternalSynth>,
-    channel: u8,
-    sample_rate: f32,
-    preset_defaults: &'a HashMap<u8, Vec<Stop>>,
-) -> &'a mut InternalSynth {
-    synths.entry(channel).or_insert_with(|| {
-        let default_stops = vec![Stop {
-            waveform: Waveform::Sine,
-            frequency_ratio: 1.0,
-            amplitude_ratio: 1.0,
-        }];
-        let stops = preset_defaults
-            .get(&(channel + 1))
-            .unwrap_or(&default_stops);
-        InternalSynth::new(sample_rate, stops.clone())
-    })
+    fn apply(&mut self, command: Command) {
+        let queues = &mut self.queues;
+        match command {
+            Command::AddDivision { channel, division } => {
+                self.divisions[channel as usize] = Some(division);
+            }
+            Command::NoteOn { channel, note } => match &mut self.divisions[channel as usize] {
+                Some(synth) => synth.add_voice(note, queues),
+                None => queues.retire(Retired::Note(note)),
+            },
+            Command::NoteOff { channel, id } => {
+                if let Some(synth) = &mut self.divisions[channel as usize] {
+                    synth.remove_voice(id);
+                }
+            }
+            Command::SetPipes { channel, id, pipes } => match &mut self.divisions[channel as usize]
+            {
+                Some(synth) => synth.set_pipes(id, pipes, queues),
+                None => queues.retire(Retired::Pipes(pipes)),
+            },
+            Command::AddPipes { channel, id, pipes } => match &mut self.divisions[channel as usize]
+            {
+                Some(synth) => synth.add_pipes(id, pipes, queues),
+                None => queues.retire(Retired::Pipes(pipes)),
+            },
+            Command::RemoveStop {
+                channel,
+                id,
+                stop,
+                ranks,
+            } => {
+                if let Some(synth) = &mut self.divisions[channel as usize] {
+                    synth.remove_stop(id, stop, ranks);
+                }
+            }
+            Command::ControlChange {
+                channel,
+                controller,
+                value,
+            } => {
+                if let Some(synth) = &mut self.divisions[channel as usize] {
+                    synth.control_change(controller, value);
+                }
+            }
+            Command::AllNotesOff { channel } => {
+                if let Some(synth) = &mut self.divisions[channel as usize] {
+                    synth.release_all();
+                }
+            }
+            Command::AllSoundOff { channel } => {
+                if let Some(synth) = &mut self.divisions[channel as usize] {
+                    synth.silence(queues);
+                }
+            }
+            Command::ResetControllers { channel } => {
+                if let Some(synth) = &mut self.divisions[channel as usize] {
+                    synth.reset_controllers();
+                }
+            }
+            Command::Panic => {
+                for synth in self.divisions.iter_mut().flatten() {
+                    synth.silence(queues);
+                    synth.reset_controllers();
+                }
+                for filter in self.master.iter_mut() {
+                    filter.reset();
+                }
+            }
+        }
+    }
 }
 
-fn handle_note_on(synth: &mut InternalSynth, message: midi::Message) {
-    let frequency = message.frequency().unwrap();
-    if message.value == 0 {
-        synth.remove_voice(frequency);
-    } else {
-        synth.add_voice(frequency);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synth() -> (Synth, Controller) {
+        let config: SynthConfig =
+            toml::from_str("stops = {}\npresets = {}\npreset_defaults = []").unwrap();
+        Synth::new_offline(48000.0, config)
     }
-}
 
-fn handle_note_off(synth: &mut InternalSynth, message: midi::Message) {
-    let frequency = message.frequency().unwrap();
-    synth.remove_voice(frequency);
-}
+    fn render(synth: &mut Synth, frames: usize) -> Vec<f32> {
+        let mut left = vec![0.0; frames];
+        let mut right = vec![0.0; frames];
+        synth.render(&mut left, &mut right);
+        left
+    }
 
-fn handle_control_change(
-    synth: &mut InternalSynth,
-    presets: &HashMap<u8, Vec<Stop>>,
-    stops: &HashMap<u8, Stop>,
-    message: midi::Message,
-) {
-    if let Some(preset) = presets.get(&message.identifier).cloned() {
-        println!("Using preset: {:?}", preset);
-        synth.use_preset(preset.to_vec());
-    } else if let Some(stop) = stops.get(&message.identifier) {
-        if message.value == 0 {
-            synth.remove_stop(*stop);
-        } else {
-            synth.add_stop(*stop);
-        }
+    #[test]
+    fn applies_immediate_commands_once_running() {
+        let (mut synth, mut controller) = synth();
+        render(&mut synth, 1000);
+        controller.handle_midi(0, &[0x90, 69, 100]);
+        let output = render(&mut synth, 1000);
+        assert_eq!(synth.frame(), 2000);
+        assert!(output.iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn applies_scheduled_commands_at_their_frame() {
+        let (mut synth, mut controller) = synth();
+        render(&mut synth, 100);
+        controller.handle_midi(150, &[0x90, 69, 100]);
+        let output = render(&mut synth, 100);
+        assert!(output[..50].iter().all(|sample| *sample == 0.0));
+        assert!(output[50..].iter().any(|sample| *sample != 0.0));
     }
-    println!("Unhandled MIDI control change: {:?}", message);
 }
@@ -1,78 +1,214 @@
-use super::{filters::Filter, note::Note, stop::Stop};
+use super::effect::{self, Effect};
+use super::filters::StereoFilter;
+use super::oscillator::Oscillator;
+use super::queue::{AudioQueues, LogEvent, Retired};
+use super::swell::Swell;
+use super::synth::BLOCK_SIZE;
+use super::tremulant::Tremulant;
+use super::wind::Wind;
+use super::{note::Note, stop::StopId, Division};
+
+/// Most notes a division sounds at once; beyond this the oldest is stolen.
+pub const VOICES_PER_DIVISION: usize = 64;
 
 // TODO this file needs to be renamed
+/// The audio thread's side of a division: its sounding notes and signal
+/// chain. Stops, compass and tuning are the control thread's business.
 pub struct InternalSynth {
-    sample_rate: f32,
-    filters: Vec<Box<dyn Filter>>,
-    stops: Vec<Stop>,
+    channel: u8,
+    filters: Vec<Box<dyn StereoFilter>>,
     notes: Vec<Note>,
+    tremulant: Option<Tremulant>,
+    wind: Option<Wind>,
+    wind_demand: f32,
+    swell: Option<Swell>,
+    gain: f32,
 }
 
 impl InternalSynth {
-    pub fn new(sample_rate: f32, stops: Vec<Stop>) -> Self {
+    pub fn new(sample_rate: f32, channel: u8, division: Division) -> Self {
         Self {
-            notes: Vec::new(),
-            sample_rate,
-            filters: vec![
-                Box::new(super::filters::LowPass::new(0.1)),
-                Box::new(super::filters::SimpleReverb::new(
-                    sample_rate,
-                    100.0,
-                    0.4,
-                    0.4,
-                )),
-            ],
-            stops,
+            channel,
+            notes: Vec::with_capacity(VOICES_PER_DIVISION),
+            filters: effect::build_chain(
+                &division.effects.unwrap_or_else(Effect::default_chain),
+                sample_rate,
+            ),
+            tremulant: division
+                .tremulant
+                .map(|settings| Tremulant::new(settings, sample_rate)),
+            wind: division
+                .wind
+                .map(|settings| Wind::new(settings, sample_rate)),
+            wind_demand: 0.0,
+            swell: division
+                .swell
+                .map(|settings| Swell::new(settings, sample_rate)),
+            gain: division.gain,
         }
     }
 
-    pub fn add_voice(&mut self, frequency: f32) {
-        let note = Note::new(frequency, self.sample_rate, &self.stops);
-        self.notes.push(note);
+    /// Handles division-level controllers, returning true if `controller`
+    /// was one of them.
+    pub fn control_change(&mut self, controller: u8, value: u8) -> bool {
+        self.tremulant
+            .as_mut()
+            .is_some_and(|tremulant| tremulant.control_change(controller, value))
+            || self
+                .wind
+                .as_mut()
+                .is_some_and(|wind| wind.control_change(controller, value))
+            || self
+                .swell
+                .as_mut()
+                .is_some_and(|swell| swell.control_change(controller, value))
     }
 
-    pub fn remove_voice(&mut self, frequency: f32) {
-        for note in &mut self.notes {
-            if note.frequency == frequency && !note.is_released {
-                note.release();
-                return;
-            }
+    pub fn reset_controllers(&mut self) {
+        if let Some(tremulant) = &mut self.tremulant {
+            tremulant.reset_controllers();
+        }
+        if let Some(wind) = &mut self.wind {
+            wind.reset_controllers();
+        }
+        if let Some(swell) = &mut self.swell {
+            swell.reset_controllers();
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.notes.iter_mut().for_each(Note::release);
+    }
+
+    /// Drops every note without a release and clears the effect tails.
+    pub fn silence(&mut self, queues: &mut AudioQueues) {
+        while let Some(note) = self.notes.pop() {
+            queues.retire(Retired::Note(note));
+        }
+        self.wind_demand = 0.0;
+        if let Some(swell) = &mut self.swell {
+            swell.reset();
+        }
+        for filter in self.filters.iter_mut() {
+            filter.reset();
         }
     }
 
-    pub fn use_preset(&mut self, stops: Vec<Stop>) {
-        self.stops = stops;
-        for note in &mut self.notes {
-            note.use_preset(&self.stops);
+    pub fn add_voice(&mut self, note: Note, queues: &mut AudioQueues) {
+        if self.notes.len() == self.notes.capacity() {
+            // Released notes are the least missed.
+            let index = self
+                .notes
+                .iter()
+                .position(|note| note.is_released)
+                .unwrap_or(0);
+            let stolen = self.notes.remove(index);
+            queues.log(LogEvent::VoiceStolen {
+                channel: self.channel,
+                key: stolen.key,
+            });
+            queues.retire(Retired::Note(stolen));
         }
+        self.notes.push(note);
     }
 
-    pub fn add_stop(&mut self, stop: Stop) {
-        self.stops.push(stop);
-        for note in &mut self.notes {
-            note.add_stop(&stop);
+    pub fn remove_voice(&mut self, id: u64) {
+        if let Some(note) = self.note(id) {
+            note.release();
         }
     }
 
-    pub fn remove_stop(&mut self, stop: Stop) {
-        let position = self.stops.iter().position(|s| s == &stop);
-        if let Some(position) = position {
-            self.stops.remove(position);
+    pub fn set_pipes(&mut self, id: u64, pipes: Vec<Oscillator>, queues: &mut AudioQueues) {
+        match self.note(id) {
+            Some(note) => queues.retire(Retired::Pipes(note.set_pipes(pipes))),
+            None => queues.retire(Retired::Pipes(pipes)),
         }
-        for note in &mut self.notes {
-            note.remove_stop(&stop);
+    }
+
+    pub fn add_pipes(&mut self, id: u64, mut pipes: Vec<Oscillator>, queues: &mut AudioQueues) {
+        let channel = self.channel;
+        if let Some(note) = self.note(id) {
+            note.add_pipes(channel, &mut pipes, queues);
         }
+        queues.retire(Retired::Pipes(pipes));
     }
 
-    pub fn next_sample(&mut self) -> f32 {
-        self.notes.retain_mut(|note| !note.is_finished());
-        let mut sample = 0.0;
+    pub fn remove_stop(&mut self, id: u64, stop: StopId, ranks: usize) {
+        if let Some(note) = self.note(id) {
+            note.remove_stop(stop, ranks);
+        }
+    }
+
+    fn note(&mut self, id: u64) -> Option<&mut Note> {
+        self.notes.iter_mut().find(|note| note.id == id)
+    }
+
+    /// Adds the next block of this division into `left` and `right`, which
+    /// must not be longer than `BLOCK_SIZE`.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32], queues: &mut AudioQueues) {
+        let len = left.len();
+        self.retire_finished(queues);
+        let mut pitch = [1.0; BLOCK_SIZE];
+        let mut gain = [1.0; BLOCK_SIZE];
+        let (pitch, gain) = (&mut pitch[..len], &mut gain[..len]);
+        if let Some(tremulant) = &mut self.tremulant {
+            for (pitch, gain) in pitch.iter_mut().zip(gain.iter_mut()) {
+                (*pitch, *gain) = tremulant.next();
+            }
+        }
+        // Demand is measured once per block; the lag is far below the
+        // reservoir's own response time.
+        if let Some(wind) = &mut self.wind {
+            for (pitch, gain) in pitch.iter_mut().zip(gain.iter_mut()) {
+                let (wind_pitch, wind_gain) = wind.next(self.wind_demand);
+                *pitch *= wind_pitch;
+                *gain *= wind_gain;
+            }
+        }
+
+        let mut division_left = [0.0; BLOCK_SIZE];
+        let mut division_right = [0.0; BLOCK_SIZE];
+        let (division_left, division_right) =
+            (&mut division_left[..len], &mut division_right[..len]);
         for note in self.notes.iter_mut() {
-            sample += note.next_sample();
+            note.render(pitch, division_left, division_right);
+        }
+        if self.wind.is_some() {
+            self.wind_demand = self.notes.iter().map(Note::wind_demand).sum();
+        }
+        for ((left, right), gain) in division_left
+            .iter_mut()
+            .zip(division_right.iter_mut())
+            .zip(gain.iter())
+        {
+            *left *= gain;
+            *right *= gain;
+        }
+        if let Some(swell) = &mut self.swell {
+            swell.process_block(division_left, division_right);
         }
         for filter in self.filters.iter_mut() {
-            sample = filter.process(sample);
+            filter.process_block(division_left, division_right);
+        }
+        for (output, sample) in left.iter_mut().zip(division_left.iter()) {
+            *output += sample * self.gain;
+        }
+        for (output, sample) in right.iter_mut().zip(division_right.iter()) {
+            *output += sample * self.gain;
+        }
+    }
+
+    /// Hands finished pipes and notes back to the control thread. Removing
+    /// in place keeps the notes in age order for voice stealing.
+    fn retire_finished(&mut self, queues: &mut AudioQueues) {
+        let mut index = 0;
+        while index < self.notes.len() {
+            self.notes[index].retire_finished(queues);
+            if self.notes[index].is_finished() {
+                queues.retire(Retired::Note(self.notes.remove(index)));
+            } else {
+                index += 1;
+            }
         }
-        sample * 0.1
     }
 }
@@ -0,0 +1,104 @@
+use super::filters::{Filter, StateVariable, StateVariableMode};
+use crate::config::ChiffConfig;
+
+/// Below this the transient is inaudible and the oscillator drops it.
+const SILENCE: f32 = 1e-4;
+/// Decay times are measured to -60 dB.
+const DECAY_RATIO: f32 = 1e-3;
+
+/// Pipe speech transient settings: a filtered noise burst ("chiff") plus a
+/// pitch and amplitude overshoot that settles as the pipe stabilizes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TransientSettings {
+    /// Peak chiff level relative to the pipe's steady tone.
+    pub level: f32,
+    /// Time in seconds for the chiff to decay by 60 dB.
+    pub duration: f32,
+    /// Centre of the chiff's band, as a multiple of the pipe's frequency.
+    pub harmonic: f32,
+    pub q: f32,
+    /// Initial pitch deviation in cents; positive starts sharp.
+    pub pitch_offset: f32,
+    /// Initial amplitude overshoot, e.g. 0.2 starts 20% louder.
+    pub overshoot: f32,
+    /// Time in seconds for pitch and amplitude to settle by 60 dB.
+    pub settle_time: f32,
+}
+
+impl TransientSettings {
+    pub fn new(config: &ChiffConfig) -> Self {
+        Self {
+            level: config.level,
+            duration: config.duration,
+            harmonic: config.harmonic,
+            q: config.q.max(0.1),
+            pitch_offset: config.pitch_offset,
+            overshoot: config.overshoot,
+            settle_time: config.settle_time,
+        }
+    }
+}
+
+pub struct Transient {
+    noise_level: f32,
+    noise_decay: f32,
+    pitch_offset: f32,
+    overshoot: f32,
+    settle_decay: f32,
+    band_pass: StateVariable,
+    seed: u32,
+}
+
+impl Transient {
+    pub fn new(settings: &TransientSettings, frequency: f32, sample_rate: f32) -> Self {
+        Self {
+            noise_level: settings.level,
+            noise_decay: decay_coefficient(settings.duration, sample_rate),
+            pitch_offset: 2.0f32.powf(settings.pitch_offset / 1200.0) - 1.0,
+            overshoot: settings.overshoot,
+            settle_decay: decay_coefficient(settings.settle_time, sample_rate),
+            band_pass: StateVariable::new(
+                StateVariableMode::BandPass,
+                sample_rate,
+                (frequency * settings.harmonic).clamp(20.0, sample_rate * 0.45),
+                settings.q,
+            ),
+            // Seeding from the pitch keeps renders reproducible.
+            seed: frequency.to_bits() | 1,
+        }
+    }
+
+    /// Returns (noise, pitch ratio, amplitude ratio) for the next sample.
+    pub fn next(&mut self) -> (f32, f32, f32) {
+        let white_noise = self.white_noise();
+        let noise = self.band_pass.process(white_noise) * self.noise_level;
+        let pitch = 1.0 + self.pitch_offset;
+        let gain = 1.0 + self.overshoot;
+        self.noise_level *= self.noise_decay;
+        self.pitch_offset *= self.settle_decay;
+        self.overshoot *= self.settle_decay;
+        (noise, pitch, gain)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.noise_level < SILENCE
+            && self.pitch_offset.abs() < SILENCE
+            && self.overshoot.abs() < SILENCE
+    }
+
+    // xorshift32: cheap, allocation-free noise that is fine for a chiff.
+    fn white_noise(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+fn decay_coefficient(time: f32, sample_rate: f32) -> f32 {
+    if time <= 0.0 {
+        0.0
+    } else {
+        DECAY_RATIO.powf(1.0 / (time * sample_rate))
+    }
+}
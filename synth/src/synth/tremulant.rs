@@ -0,0 +1,102 @@
+use crate::config::TremulantConfig;
+use std::f32::consts::{LN_2, TAU};
+
+/// Time for the tremulant to reach full depth after being switched on (or to
+/// die away after being switched off), like the beater winding up.
+const RAMP_TIME: f32 = 0.3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TremulantSettings {
+    pub enabled: bool,
+    /// LFO rate in Hz.
+    pub speed: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// Peak amplitude deviation as a fraction of the steady level.
+    pub amplitude_depth: f32,
+    /// Peak pitch deviation in cents.
+    pub pitch_depth: f32,
+    pub toggle_cc: Option<u8>,
+    pub speed_cc: Option<u8>,
+    pub depth_cc: Option<u8>,
+}
+
+impl TremulantSettings {
+    pub fn new(config: &TremulantConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            speed: config.speed,
+            min_speed: config.min_speed,
+            max_speed: config.max_speed,
+            amplitude_depth: config.amplitude_depth,
+            pitch_depth: config.pitch_depth,
+            toggle_cc: config.toggle_cc,
+            speed_cc: config.speed_cc,
+            depth_cc: config.depth_cc,
+        }
+    }
+}
+
+/// Modulates the pitch and amplitude of a whole division.
+pub struct Tremulant {
+    settings: TremulantSettings,
+    enabled: bool,
+    speed: f32,
+    depth: f32,
+    intensity: f32,
+    phase: f32,
+    sample_rate: f32,
+    ramp: f32,
+}
+
+impl Tremulant {
+    pub fn new(settings: TremulantSettings, sample_rate: f32) -> Self {
+        Self {
+            settings,
+            enabled: settings.enabled,
+            speed: settings.speed,
+            depth: 1.0,
+            intensity: if settings.enabled { 1.0 } else { 0.0 },
+            phase: 0.0,
+            sample_rate,
+            ramp: (-1.0 / (RAMP_TIME * sample_rate)).exp(),
+        }
+    }
+
+    /// Applies a control change, returning true if it belonged to this
+    /// tremulant. The toggle switches on at 64 and above; the speed CC sweeps
+    /// from `min_speed` to `max_speed`; the depth CC scales the configured
+    /// depths from 0 to 2, with 64 leaving them as configured.
+    pub fn control_change(&mut self, controller: u8, value: u8) -> bool {
+        let normalized = value as f32 / 127.0;
+        if Some(controller) == self.settings.toggle_cc {
+            self.enabled = value >= 64;
+        } else if Some(controller) == self.settings.speed_cc {
+            self.speed = self.settings.min_speed
+                + (self.settings.max_speed - self.settings.min_speed) * normalized;
+        } else if Some(controller) == self.settings.depth_cc {
+            self.depth = value as f32 / 64.0;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Returns switch, speed and depth to their configured values.
+    pub fn reset_controllers(&mut self) {
+        self.enabled = self.settings.enabled;
+        self.speed = self.settings.speed;
+        self.depth = 1.0;
+    }
+
+    /// Returns (pitch ratio, amplitude ratio) for the next sample.
+    pub fn next(&mut self) -> (f32, f32) {
+        let target = if self.enabled { self.depth } else { 0.0 };
+        self.intensity = target + (self.intensity - target) * self.ramp;
+        self.phase = (self.phase + self.speed / self.sample_rate) % 1.0;
+        let lfo = (TAU * self.phase).sin() * self.intensity;
+        let pitch = (lfo * self.settings.pitch_depth * LN_2 / 1200.0).exp();
+        let gain = 1.0 + lfo * self.settings.amplitude_depth;
+        (pitch, gain)
+    }
+}
@@ -0,0 +1,405 @@
+use crate::config::TuningConfig;
+use std::fs;
+
+/// Deviation of each pitch class from equal temperament in cents, C to B.
+const TEMPERAMENTS: [(&str, [f64; 12]); 6] = [
+    ("equal", [0.0; 12]),
+    // Pure fifths from Eb to G#, with the wolf between G# and Eb.
+    (
+        "pythagorean",
+        [
+            0.0, 13.7, 3.9, -5.9, 7.8, -2.0, 11.7, 2.0, 15.6, 5.9, -3.9, 9.8,
+        ],
+    ),
+    // Quarter-comma, with the wolf between G# and Eb.
+    (
+        "meantone",
+        [
+            10.3, -13.7, 3.4, 20.5, -3.4, 13.7, -10.3, 6.8, -17.1, 0.0, 17.1, -6.8,
+        ],
+    ),
+    (
+        "werckmeister3",
+        [
+            0.0, -9.8, -7.8, -5.9, -9.8, -2.0, -11.7, -3.9, -7.8, -11.7, -3.9, -7.8,
+        ],
+    ),
+    (
+        "kirnberger3",
+        [
+            0.0, -9.8, -6.8, -5.9, -13.7, -2.0, -11.7, -3.4, -7.8, -10.3, -3.9, -11.7,
+        ],
+    ),
+    (
+        "vallotti",
+        [
+            5.9, 0.0, 2.0, 3.9, -2.0, 7.8, -2.0, 3.9, 2.0, 0.0, 5.9, -3.9,
+        ],
+    ),
+];
+
+/// Maps MIDI keys to frequencies. Built-in temperaments and Scala files are
+/// both expressed as a scale plus a keyboard mapping, as in Scala itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    pub name: String,
+    /// Cents of scale degrees 1..=n; the last one is the period.
+    degrees: Vec<f64>,
+    keyboard: KeyboardMap,
+}
+
+/// The contents of a Scala `.kbm` file.
+#[derive(Debug, Clone, PartialEq)]
+struct KeyboardMap {
+    first_key: u8,
+    last_key: u8,
+    /// Key that plays scale degree 0.
+    middle_key: i32,
+    reference_key: i32,
+    reference_frequency: f64,
+    /// Scale degree that acts as the formal octave of the mapping.
+    octave_degree: usize,
+    /// Scale degree per key in the pattern; `None` leaves a key silent.
+    /// Empty means one key per scale degree.
+    mapping: Vec<Option<usize>>,
+}
+
+impl KeyboardMap {
+    fn linear(reference_pitch: f32) -> Self {
+        Self {
+            first_key: 0,
+            last_key: 127,
+            middle_key: 60,
+            reference_key: 69,
+            reference_frequency: reference_pitch as f64,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+}
+
+impl Tuning {
+    pub fn new(config: &TuningConfig) -> Result<Self, String> {
+        match &config.scala {
+            Some(path) => Self::scala(path, config.keyboard_map.as_deref(), config.reference_pitch),
+            None => Self::temperament(&config.temperament, config.reference_pitch)
+                .ok_or_else(|| format!("Unknown temperament: {:?}", config.temperament)),
+        }
+    }
+
+    pub fn temperament(name: &str, reference_pitch: f32) -> Option<Self> {
+        let (_, offsets) = TEMPERAMENTS.iter().find(|(n, _)| *n == name)?;
+        let degrees = (1..=12)
+            .map(|degree| degree as f64 * 100.0 + offsets[degree % 12] - offsets[0])
+            .collect();
+        Some(Self {
+            name: name.to_string(),
+            degrees,
+            keyboard: KeyboardMap::linear(reference_pitch),
+        })
+    }
+
+    pub fn temperament_names() -> impl Iterator<Item = &'static str> {
+        TEMPERAMENTS.iter().map(|(name, _)| *name)
+    }
+
+    /// Loads a Scala scale, with an optional keyboard mapping. Without one,
+    /// degree 0 is on middle C and A4 sounds at `reference_pitch`.
+    pub fn scala(
+        scale_path: &str,
+        keyboard_path: Option<&str>,
+        reference_pitch: f32,
+    ) -> Result<Self, String> {
+        let read = |path: &str| {
+            fs::read_to_string(path).map_err(|e| format!("Error reading {:?}: {}", path, e))
+        };
+        let scale = read(scale_path)?;
+        let keyboard = keyboard_path.map(read).transpose()?;
+        Self::parse_scala(&scale, keyboard.as_deref(), reference_pitch)
+    }
+
+    fn parse_scala(
+        scale: &str,
+        keyboard: Option<&str>,
+        reference_pitch: f32,
+    ) -> Result<Self, String> {
+        let tuning = Self {
+            name: "scala".to_string(),
+            degrees: parse_scl(scale)?,
+            keyboard: match keyboard {
+                Some(text) => parse_kbm(text)?,
+                None => KeyboardMap::linear(reference_pitch),
+            },
+        };
+        // Every key is tuned relative to the reference key, so without it
+        // nothing would sound.
+        if tuning.cents(tuning.keyboard.reference_key).is_none() {
+            return Err(format!(
+                "Keyboard mapping leaves its reference key {} unmapped",
+                tuning.keyboard.reference_key
+            ));
+        }
+        Ok(tuning)
+    }
+
+    /// Returns `None` for keys the keyboard mapping leaves unmapped.
+    pub fn frequency(&self, key: u8) -> Option<f32> {
+        let cents = self.cents(key as i32)?;
+        // Checked when the mapping is loaded.
+        let reference = self.cents(self.keyboard.reference_key)?;
+        Some((self.keyboard.reference_frequency * 2f64.powf((cents - reference) / 1200.0)) as f32)
+    }
+
+    fn cents(&self, key: i32) -> Option<f64> {
+        let keyboard = &self.keyboard;
+        if key < keyboard.first_key as i32 || key > keyboard.last_key as i32 {
+            return None;
+        }
+        let offset = key - keyboard.middle_key;
+        if keyboard.mapping.is_empty() {
+            return Some(self.degree_cents(offset));
+        }
+        let size = keyboard.mapping.len() as i32;
+        let degree = keyboard.mapping[offset.rem_euclid(size) as usize]?;
+        let octave = self.degree_cents(keyboard.octave_degree as i32);
+        Some(offset.div_euclid(size) as f64 * octave + self.degree_cents(degree as i32))
+    }
+
+    fn degree_cents(&self, degree: i32) -> f64 {
+        let size = self.degrees.len() as i32;
+        let period = self.degrees[self.degrees.len() - 1];
+        let index = degree.rem_euclid(size);
+        let within = if index == 0 {
+            0.0
+        } else {
+            self.degrees[index as usize - 1]
+        };
+        degree.div_euclid(size) as f64 * period + within
+    }
+}
+
+/// Lines of a Scala file with comments (`!`) removed.
+fn scala_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('!'))
+}
+
+fn parse_scl(text: &str) -> Result<Vec<f64>, String> {
+    let mut lines = scala_lines(text);
+    let _description = lines.next();
+    let count: usize = lines
+        .next()
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|count| count.parse().ok())
+        .ok_or("Scala file has no note count")?;
+    let degrees = lines
+        .filter(|line| !line.is_empty())
+        .take(count)
+        .map(parse_pitch)
+        .collect::<Result<Vec<_>, _>>()?;
+    if degrees.len() != count || count == 0 {
+        return Err(format!(
+            "Scala file declares {} notes but has {}",
+            count,
+            degrees.len()
+        ));
+    }
+    Ok(degrees)
+}
+
+/// A pitch is in cents if it contains a period, otherwise a ratio or integer.
+fn parse_pitch(line: &str) -> Result<f64, String> {
+    let value = line.split_whitespace().next().unwrap_or("");
+    let error = || format!("Invalid Scala pitch: {:?}", line);
+    if value.contains('.') {
+        return value.parse().map_err(|_| error());
+    }
+    let (numerator, denominator) = value.split_once('/').unwrap_or((value, "1"));
+    let numerator: f64 = numerator.parse().map_err(|_| error())?;
+    let denominator: f64 = denominator.parse().map_err(|_| error())?;
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return Err(error());
+    }
+    Ok(1200.0 * (numerator / denominator).log2())
+}
+
+fn parse_kbm(text: &str) -> Result<KeyboardMap, String> {
+    let mut values = scala_lines(text)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.split_whitespace().next().unwrap_or(""));
+    let mut next = |name: &str| {
+        values
+            .next()
+            .ok_or_else(|| format!("Keyboard mapping is missing {}", name))
+    };
+    let number = |value: &str, name: &str| {
+        value
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid keyboard mapping {}: {:?}", name, value))
+    };
+    let size = number(next("map size")?, "map size")? as usize;
+    let first_key = number(next("first note")?, "first note")? as u8;
+    let last_key = number(next("last note")?, "last note")? as u8;
+    let middle_key = number(next("middle note")?, "middle note")? as i32;
+    let reference_key = number(next("reference note")?, "reference note")? as i32;
+    let reference_frequency = number(next("reference frequency")?, "reference frequency")?;
+    let octave_degree = number(next("octave degree")?, "octave degree")? as usize;
+    let mapping = (0..size)
+        .map(|_| {
+            let value = next("mapping entry")?;
+            if value == "x" {
+                Ok(None)
+            } else {
+                Ok(Some(number(value, "mapping entry")? as usize))
+            }
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(KeyboardMap {
+        first_key,
+        last_key,
+        middle_key,
+        reference_key,
+        reference_frequency,
+        octave_degree,
+        mapping,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PURE_FIFTH: f64 = 701.955;
+    const SYNTONIC_COMMA: f64 = 21.506;
+    const PYTHAGOREAN_COMMA: f64 = 23.460;
+    const SCHISMA: f64 = 1.954;
+
+    /// Size of each fifth, by the pitch class it starts on, as each
+    /// temperament is designed: pure unless tempered here. Tables are given
+    /// to a tenth of a cent.
+    fn designed_fifths(name: &str) -> [f64; 12] {
+        let mut fifths = [PURE_FIFTH; 12];
+        let mut temper = |classes: &[usize], amount: f64| {
+            for &class in classes {
+                fifths[class] = PURE_FIFTH - amount;
+            }
+        };
+        match name {
+            "equal" => temper(&(0..12).collect::<Vec<_>>(), PYTHAGOREAN_COMMA / 12.0),
+            "pythagorean" => {}
+            "meantone" => temper(&[0, 1, 2, 3, 4, 5, 6, 7, 9, 10, 11], SYNTONIC_COMMA / 4.0),
+            "werckmeister3" => temper(&[0, 7, 2, 11], PYTHAGOREAN_COMMA / 4.0),
+            "kirnberger3" => {
+                temper(&[0, 7, 2, 9], SYNTONIC_COMMA / 4.0);
+                temper(&[11], SCHISMA);
+            }
+            "vallotti" => temper(&[5, 0, 7, 2, 9, 4], PYTHAGOREAN_COMMA / 6.0),
+            _ => panic!("No design for {}", name),
+        }
+        fifths
+    }
+
+    /// The fifth the circle is closed on, if the temperament has one.
+    fn wolf(name: &str) -> Option<usize> {
+        match name {
+            "pythagorean" | "meantone" => Some(8),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn temperament_fifths_match_their_design() {
+        for (name, offsets) in TEMPERAMENTS {
+            let designed = designed_fifths(name);
+            for class in 0..12 {
+                if wolf(name) == Some(class) {
+                    continue;
+                }
+                let fifth = 700.0 + offsets[(class + 7) % 12] - offsets[class];
+                assert!(
+                    (fifth - designed[class]).abs() < 0.1,
+                    "{}: fifth on pitch class {} is {:.2} cents, expected {:.2}",
+                    name,
+                    class,
+                    fifth,
+                    designed[class]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn temperaments_tune_the_reference_pitch() {
+        for name in Tuning::temperament_names() {
+            let tuning = Tuning::temperament(name, 415.0).unwrap();
+            assert!(
+                (tuning.frequency(69).unwrap() - 415.0).abs() < 1e-3,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn parses_scl_with_comments_and_all_pitch_forms() {
+        let text = "! meantone.scl\n!\nQuarter-comma meantone\n 4\n!\n76.049 cents\n9/8\n\n  5/4 ! third\n2\n";
+        let degrees = parse_scl(text).unwrap();
+        let expected = [76.049, 203.910, 386.314, 1200.0];
+        assert_eq!(degrees.len(), expected.len());
+        for (degree, expected) in degrees.iter().zip(expected) {
+            assert!(
+                (degree - expected).abs() < 1e-3,
+                "{} != {}",
+                degree,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_scl() {
+        assert!(parse_scl("").is_err());
+        assert!(parse_scl("Description\nmany\n").is_err());
+        assert!(parse_scl("Description\n2\n100.0\n").is_err());
+        assert!(parse_scl("Description\n0\n").is_err());
+        assert!(parse_scl("Description\n1\nfifth\n").is_err());
+        assert!(parse_scl("Description\n1\n3/0\n").is_err());
+        assert!(parse_scl("Description\n1\n-3/2\n").is_err());
+        assert!(parse_scl("Description\n1\n1.2.3\n").is_err());
+    }
+
+    const SCALE: &str = "12-TET\n12\n100.0\n200.0\n300.0\n400.0\n500.0\n600.0\n700.0\n800.0\n900.0\n1000.0\n1100.0\n2/1\n";
+
+    #[test]
+    fn parses_kbm_with_comments_and_unmapped_keys() {
+        let keyboard =
+            "! white keys only\n7\n0\n127\n60\n60\n261.626\n12\n! mapping\n0\nx\n2\nx\n4\n5\n7\n";
+        let map = parse_kbm(keyboard).unwrap();
+        assert_eq!(map.mapping.len(), 7);
+        assert_eq!(map.mapping[1], None);
+        assert_eq!(map.octave_degree, 12);
+        let tuning = Tuning::parse_scala(SCALE, Some(keyboard), 440.0).unwrap();
+        assert_eq!(tuning.frequency(61), None);
+        assert!((tuning.frequency(62).unwrap() - 293.665).abs() < 1e-2);
+        // Seven keys on, the pattern repeats an octave up.
+        assert!((tuning.frequency(67).unwrap() - 523.252).abs() < 1e-2);
+    }
+
+    #[test]
+    fn rejects_malformed_kbm() {
+        assert!(parse_kbm("").is_err());
+        assert!(parse_kbm("12\n0\n127\n60\n69\n").is_err());
+        assert!(parse_kbm("1\n0\n127\n60\n69\nA4\n12\n0\n").is_err());
+        assert!(parse_kbm("2\n0\n127\n60\n69\n440.0\n12\n0\n").is_err());
+        assert!(parse_kbm("1\n0\n127\n60\n69\n440.0\n12\ny\n").is_err());
+    }
+
+    #[test]
+    fn rejects_kbm_with_unusable_reference_key() {
+        let unmapped = "2\n0\n127\n60\n61\n440.0\n12\n0\nx\n";
+        assert!(Tuning::parse_scala(SCALE, Some(unmapped), 440.0).is_err());
+        let out_of_range = "0\n0\n60\n60\n69\n440.0\n12\n";
+        assert!(Tuning::parse_scala(SCALE, Some(out_of_range), 440.0).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::Path;
+
+/// A WAV file decoded to floats in -1 to 1, one vector per channel.
+pub struct Wav {
+    pub sample_rate: f32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl Wav {
+    pub fn load(path: &Path) -> Result<Self, hound::Error> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let interleaved: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 * scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        let channel_count = spec.channels.max(1) as usize;
+        let channels = (0..channel_count)
+            .map(|channel| {
+                interleaved
+                    .iter()
+                    .skip(channel)
+                    .step_by(channel_count)
+                    .copied()
+                    .collect()
+            })
+            .collect();
+        Ok(Self {
+            sample_rate: spec.sample_rate as f32,
+            channels,
+        })
+    }
+}
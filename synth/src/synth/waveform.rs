@@ -1,21 +1,35 @@
-#[derive(Debug, Copy, Clone, PartialEq)]
+use super::additive::HarmonicTable;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
 pub enum Waveform {
     Sine,
     Square,
     Sawtooth,
     Triangle,
+    Additive(Arc<HarmonicTable>),
 }
 
-// TODO clean up this file!
-impl Waveform {
-    pub fn str(&self) -> &str {
-        match self {
-            Waveform::Sine => "sine",
-            Waveform::Square => "square",
-            Waveform::Sawtooth => "sawtooth",
-            Waveform::Triangle => "triangle",
+/// How discontinuous waveforms are generated. Naive generation aliases
+/// audibly on high pitches; PolyBLEP smooths each discontinuity with a
+/// polynomial band-limited step.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Antialiasing {
+    None,
+    PolyBlep,
+}
+
+impl Antialiasing {
+    pub fn parse(antialiasing: &str) -> Self {
+        match antialiasing {
+            "none" | "naive" => Antialiasing::None,
+            _ => Antialiasing::PolyBlep,
         }
     }
+}
+
+// TODO clean up this file!
+impl Waveform {
     pub fn parse(waveform: &str) -> Self {
         match waveform {
             "sine" => Waveform::Sine,
@@ -26,15 +40,84 @@ impl Waveform {
         }
     }
 
-    pub fn generate_sample(&self, phase: f32, frequency: f32) -> f32 {
-        match self {
-            Waveform::Sine => Self::generate_sine_sample(phase),
-            Waveform::Square => Self::generate_square_sample(phase),
-            Waveform::Sawtooth => Self::generate_sawtooth_sample(phase),
-            Waveform::Triangle => Self::generate_organ_sample(phase, frequency),
+    /// Builds an additive waveform from a list of harmonic amplitudes and
+    /// optional starting phases (in cycles).
+    pub fn additive(harmonics: &[f32], phases: &[f32]) -> Self {
+        Waveform::Additive(Arc::new(HarmonicTable::new(harmonics, phases)))
+    }
+
+    /// Fills `output` with the wave at each of `phases`. `phase_increment`
+    /// is the phase advanced per sample (frequency / sample rate), and
+    /// `organ_blend` comes from `organ_blend` for the pipe's frequency. The
+    /// waveform is matched once, so each loop is branch-free per sample.
+    pub fn render(
+        &self,
+        antialiasing: Antialiasing,
+        phases: &[f32],
+        phase_increment: f32,
+        organ_blend: f32,
+        output: &mut [f32],
+    ) {
+        let samples = output.iter_mut().zip(phases);
+        match (self, antialiasing) {
+            (Waveform::Sine, _) => {
+                for (output, &phase) in samples {
+                    *output = Self::generate_sine_sample(phase);
+                }
+            }
+            (Waveform::Square, Antialiasing::None) => {
+                for (output, &phase) in samples {
+                    *output = Self::generate_square_sample(phase);
+                }
+            }
+            (Waveform::Square, Antialiasing::PolyBlep) => {
+                for (output, &phase) in samples {
+                    let half = if phase < 0.5 {
+                        phase + 0.5
+                    } else {
+                        phase - 0.5
+                    };
+                    *output = Self::generate_square_sample(phase)
+                        + poly_blep(phase, phase_increment)
+                        - poly_blep(half, phase_increment);
+                }
+            }
+            (Waveform::Sawtooth, Antialiasing::None) => {
+                for (output, &phase) in samples {
+                    *output = Self::generate_sawtooth_sample(phase);
+                }
+            }
+            (Waveform::Sawtooth, Antialiasing::PolyBlep) => {
+                for (output, &phase) in samples {
+                    *output =
+                        Self::generate_sawtooth_sample(phase) - poly_blep(phase, phase_increment);
+                }
+            }
+            (Waveform::Triangle, _) => {
+                for (output, &phase) in samples {
+                    *output = Self::generate_organ_sample(phase, organ_blend);
+                }
+            }
+            (Waveform::Additive(table), _) => {
+                for (output, &phase) in samples {
+                    *output = table.sample(phase, phase_increment);
+                }
+            }
         }
     }
 
+    /// How far the organ wave leans from sine (0) to triangle (1) at
+    /// `frequency`. Fixed per pipe, so it is worked out once.
+    pub fn organ_blend(frequency: f32) -> f32 {
+        // Parameters for the logarithmic curve
+        let min_freq = 20.0; // Lowest frequency (e.g., 20 Hz)
+        let max_freq = 100.0; // Highest frequency where the blend reaches mostly triangle
+        let log_base = 10.0; // Base of the logarithm, can tweak for smoothness
+
+        // Normalize frequency to a 0-1 range logarithmically
+        ((frequency / min_freq).log(log_base) / (max_freq / min_freq).log(log_base)).clamp(0.0, 1.0)
+    }
+
     fn generate_sine_sample(phase: f32) -> f32 {
         (2.0 * std::f32::consts::PI * phase).sin()
     }
@@ -51,31 +134,11 @@ impl Waveform {
         2.0 * (phase - 0.5)
     }
 
-    fn generate_organ_sample(phase: f32, frequency: f32) -> f32 {
-        // Parameters for the logarithmic curve
-        let min_freq = 20.0; // Lowest frequency (e.g., 20 Hz)
-        let max_freq = 100.0; // Highest frequency where the blend reaches mostly triangle
-        let log_base = 10.0; // Base of the logarithm, can tweak for smoothness
-
-        // Normalize frequency to a 0-1 range logarithmically
-        let normalized_freq = ((frequency / min_freq).log(log_base)
-            / (max_freq / min_freq).log(log_base))
-        .clamp(0.0, 1.0);
-
-        // Blend factor is the normalized logarithmic value
-        let blend_factor = normalized_freq;
-
-        // println!("Freq: {} Blend factor: {}", frequency, blend_factor);
-
-        // 2. Generate the two waveforms:
+    fn generate_organ_sample(phase: f32, blend_factor: f32) -> f32 {
         let sine = (phase * 2.0 * std::f32::consts::PI).sin();
-        let triangle = if phase < 0.5 {
-            4.0 * phase - 1.0
-        } else {
-            3.0 - 4.0 * phase
-        };
+        let triangle = Self::generate_triangle_sample(phase);
 
-        // 3. Crossfade between sine and triangle
+        // Crossfade between sine and triangle
         //    blend_factor = 0 => all sine
         //    blend_factor = 1 => all triangle
         (1.0 - blend_factor) * sine + blend_factor * triangle
@@ -89,3 +152,17 @@ impl Waveform {
         }
     }
 }
+
+/// Residual of a band-limited unit step around a discontinuity at phase 0,
+/// spread over one sample on either side.
+fn poly_blep(phase: f32, phase_increment: f32) -> f32 {
+    if phase < phase_increment {
+        let t = phase / phase_increment;
+        t + t - t * t - 1.0
+    } else if phase > 1.0 - phase_increment {
+        let t = (phase - 1.0) / phase_increment;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
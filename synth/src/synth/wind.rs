@@ -0,0 +1,106 @@
+use crate::config::WindConfig;
+use std::f32::consts::LN_2;
+
+/// Pitch of a pipe whose wind consumption counts as one unit (middle C).
+const REFERENCE_FREQUENCY: f32 = 261.63;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WindSettings {
+    /// Fraction of pressure lost when the demand is far above `capacity`.
+    pub sag: f32,
+    /// Demand at which half of `sag` is lost. One unit is roughly one 8'
+    /// middle C pipe at full amplitude.
+    pub capacity: f32,
+    /// Seconds for pressure to drop when pipes start speaking.
+    pub response: f32,
+    /// Seconds for the reservoir to recover pressure when demand falls.
+    pub recovery: f32,
+    /// Pitch change in cents for a 100% change in pressure.
+    pub pitch_depth: f32,
+    /// Amplitude change as a fraction for a 100% change in pressure.
+    pub amplitude_depth: f32,
+    pub pressure_cc: Option<u8>,
+}
+
+impl WindSettings {
+    pub fn new(config: &WindConfig) -> Self {
+        Self {
+            sag: config.sag.clamp(0.0, 1.0),
+            capacity: config.capacity.max(f32::EPSILON),
+            response: config.response,
+            recovery: config.recovery,
+            pitch_depth: config.pitch_depth,
+            amplitude_depth: config.amplitude_depth,
+            pressure_cc: config.pressure_cc,
+        }
+    }
+}
+
+/// How much wind a pipe consumes relative to the reference pipe: larger
+/// (lower) pipes and louder stops draw more.
+pub fn pipe_demand(amplitude: f32, frequency: f32) -> f32 {
+    amplitude * (REFERENCE_FREQUENCY / frequency.max(1.0)).sqrt()
+}
+
+/// Wind supply shared by a division: sounding pipes lower the pressure,
+/// which flattens and softens every pipe until the reservoir recovers.
+pub struct Wind {
+    settings: WindSettings,
+    nominal: f32,
+    pressure: f32,
+    response: f32,
+    recovery: f32,
+}
+
+impl Wind {
+    pub fn new(settings: WindSettings, sample_rate: f32) -> Self {
+        Self {
+            settings,
+            nominal: 1.0,
+            pressure: 1.0,
+            response: smoothing_coefficient(settings.response, sample_rate),
+            recovery: smoothing_coefficient(settings.recovery, sample_rate),
+        }
+    }
+
+    /// Applies a control change, returning true if it set the pressure. The
+    /// pressure CC spans half to one and a half times nominal pressure, with
+    /// 64 as nominal.
+    pub fn control_change(&mut self, controller: u8, value: u8) -> bool {
+        if Some(controller) != self.settings.pressure_cc {
+            return false;
+        }
+        self.nominal = 0.5 + value as f32 / 128.0;
+        true
+    }
+
+    /// Returns to nominal pressure.
+    pub fn reset_controllers(&mut self) {
+        self.nominal = 1.0;
+    }
+
+    /// Advances the reservoir by one sample given the current total `demand`
+    /// and returns (pitch ratio, amplitude ratio).
+    pub fn next(&mut self, demand: f32) -> (f32, f32) {
+        let load = demand / (demand + self.settings.capacity);
+        let target = self.nominal * (1.0 - self.settings.sag * load);
+        let coefficient = if target < self.pressure {
+            self.response
+        } else {
+            self.recovery
+        };
+        self.pressure = target + (self.pressure - target) * coefficient;
+        let deviation = self.pressure - 1.0;
+        let pitch = (deviation * self.settings.pitch_depth * LN_2 / 1200.0).exp();
+        let gain = (1.0 + deviation * self.settings.amplitude_depth).max(0.0);
+        (pitch, gain)
+    }
+}
+
+fn smoothing_coefficient(time: f32, sample_rate: f32) -> f32 {
+    if time <= 0.0 {
+        0.0
+    } else {
+        (-1.0 / (time * sample_rate)).exp()
+    }
+}